@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// operators that stay searchable in code mode, longest first so `::` wins over `:`
+const OPERATORS: [&str; 16] = [
+    "...", "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "<<", ">>", "..", "?.",
+];
+
+// compiled on first use, every OCR word is tokenized on its own
+static WORD: OnceLock<Regex> = OnceLock::new();
+static COMPOUND: OnceLock<Regex> = OnceLock::new();
+static OPERATOR: OnceLock<Regex> = OnceLock::new();
+
+fn word() -> &'static Regex {
+    return WORD.get_or_init(|| Regex::new(r"\w+").unwrap());
+}
+
+fn compound() -> &'static Regex {
+    return COMPOUND.get_or_init(|| Regex::new(r"\w+(?:(?:::|->|\.)\w+)+").unwrap());
+}
+
+fn operator() -> &'static Regex {
+    return OPERATOR.get_or_init(|| {
+        let operators = OPERATORS
+            .iter()
+            .map(|op| regex::escape(op))
+            .collect::<Vec<_>>()
+            .join("|");
+        Regex::new(&operators).unwrap()
+    });
+}
+
+/// how text is split into index terms, queries are split the same way
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Analyzer {
+    /// lowercase words
+    #[default]
+    Text,
+    /// words, plus compound identifiers like `hashmap::new`, their camelCase and snake_case
+    /// parts, and operators
+    Code,
+}
+
+impl FromStr for Analyzer {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Analyzer::Text),
+            "code" => Ok(Analyzer::Code),
+            _ => Err(format!("unknown analyzer '{}', expected text or code", s)),
+        }
+    }
+}
+
+impl Analyzer {
+    pub fn tokenize(&self, text: &str) -> HashSet<String> {
+        let mut words: HashSet<String> = word()
+            .find_iter(&text.trim().to_lowercase())
+            .map(|m| m.as_str().to_string())
+            .collect();
+        if *self == Analyzer::Code {
+            words.extend(code_terms(text));
+        }
+        return words;
+    }
+}
+
+fn code_terms(text: &str) -> Vec<String> {
+    let mut terms: Vec<String> = compound()
+        .find_iter(text)
+        .map(|m| m.as_str().to_lowercase())
+        .collect();
+    for identifier in word().find_iter(text) {
+        terms.extend(
+            subwords(identifier.as_str())
+                .iter()
+                .map(|w| w.to_lowercase()),
+        );
+    }
+    terms.extend(operator().find_iter(text).map(|m| m.as_str().to_string()));
+    return terms;
+}
+
+/// splits `parseFrameRate`, `get_video_dims` and `HTTPServer` into their parts,
+/// an identifier with a single part gives nothing
+pub fn subwords(identifier: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    for part in identifier.split('_').filter(|p| !p.is_empty()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (previous, current) = (chars[i - 1].1, chars[i].1);
+            let next_is_lower = chars.get(i + 1).is_some_and(|(_, c)| c.is_lowercase());
+            // fooBar, or the last capital of an acronym: HTTPServer
+            let boundary = (previous.is_lowercase() && current.is_uppercase())
+                || (previous.is_uppercase() && current.is_uppercase() && next_is_lower);
+            if boundary {
+                parts.push(&part[start..chars[i].0]);
+                start = chars[i].0;
+            }
+        }
+        parts.push(&part[start..]);
+    }
+    if parts.len() < 2 {
+        return Vec::new();
+    }
+    return parts;
+}
@@ -1,71 +1,393 @@
 use crate::config::constants::NUM_THREADS;
+use crate::subtitles;
 use hound::{SampleFormat, WavReader};
-use std::{path::Path, process::Command};
+use serde_json::Value;
+use std::{fs, path::Path, process::Command, str::FromStr};
 use whisper_rs::{FullParams, SamplingStrategy, WhisperContext};
-/// Returns ctx segments: (word, timestamp)
-pub fn asr(audio_path: &Path) -> WhisperContext {
-    let whisper_path = Path::new("models/cpp_whisper");
 
-    let model_path = Path::new(whisper_path).join("models/ggml-base.en.bin");
+/// the language whisper detects the spoken language from the audio
+pub const AUTO_LANGUAGE: &str = "auto";
+/// whisper only keeps the last 224 tokens of the text it continues from
+const MAX_PROMPT_TOKENS: usize = 224;
 
-    // whisper_cpp(&audio_path, whisper_path, &model_path);
-    // println!("DONE");
-    return whisper_rs(audio_path, &model_path);
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsrSettings {
+    /// ggml whisper model, `*.en.bin` models only transcribe English
+    pub model: String,
+    /// whisper language code, e.g. `en` or `de`, or `auto` to detect it
+    pub language: String,
+    /// text whisper continues from, steers it towards the glossary's spelling, empty for none
+    pub prompt: String,
+    pub backend: AsrBackend,
+    /// directory of the whisper.cpp executable, for the `whisper-cpp` backend
+    pub whisper_cpp: String,
+    /// subtitle file the `mock` backend replays
+    pub mock_file: Option<String>,
 }
-fn whisper_rs(audio_path: &Path, model_path: &Path) -> WhisperContext {
-    let original_samples = parse_wav_file(audio_path);
-    let samples = whisper_rs::convert_integer_to_float_audio(&original_samples);
-    let mut ctx = WhisperContext::new(&model_path.to_string_lossy()).expect("failed to open model");
-    let mut params = FullParams::new(SamplingStrategy::default());
-    params.set_n_threads(NUM_THREADS);
-    params.set_language(Some("en"));
-    params.set_translate(false);
-    params.set_print_special(false);
-    params.set_print_progress(false);
-    params.set_print_realtime(false);
-    ctx.full(params, &samples)
-        .expect("failed to convert samples");
 
-    return ctx;
+impl Default for AsrSettings {
+    fn default() -> Self {
+        return Self {
+            model: "models/cpp_whisper/models/ggml-base.en.bin".to_string(),
+            language: "en".to_string(),
+            prompt: String::new(),
+            backend: AsrBackend::WhisperRs,
+            whisper_cpp: "models/cpp_whisper".to_string(),
+            mock_file: None,
+        };
+    }
+}
+
+impl AsrSettings {
+    pub fn is_english_only(&self) -> bool {
+        return self.model.ends_with(".en.bin");
+    }
+}
+
+/// a word piece of a segment, times in ms
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub start: u64,
+    pub end: u64,
+    /// 0 to 1, how sure the model was of the token
+    pub prob: f32,
+}
+
+/// a transcribed stretch of speech, times in ms
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+    pub tokens: Vec<Token>,
+}
+
+pub trait AsrEngine {
+    /// transcribes a 16kHz mono WAV file
+    fn transcribe(&mut self, audio_path: &Path) -> Result<Vec<Segment>, String>;
+    /// the spoken language, as set or detected by the last `transcribe`
+    fn language(&self) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AsrBackend {
+    /// whisper.cpp linked through whisper-rs
+    #[default]
+    WhisperRs,
+    /// runs the whisper.cpp executable in `whisper_cpp`
+    WhisperCpp,
+    /// replays the subtitle file in `mock_file`, see `MockEngine`
+    Mock,
+}
+
+impl FromStr for AsrBackend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whisper-rs" | "whisper_rs" => Ok(AsrBackend::WhisperRs),
+            "whisper-cpp" | "whisper_cpp" => Ok(AsrBackend::WhisperCpp),
+            "mock" => Ok(AsrBackend::Mock),
+            _ => Err(format!("unknown ASR backend '{}'", s)),
+        }
+    }
 }
-fn parse_wav_file(path: &Path) -> Vec<i16> {
-    let reader = WavReader::open(path).expect("failed to read file");
 
-    if reader.spec().channels != 1 {
-        panic!("expected mono audio file");
+pub fn make_engine(settings: &AsrSettings) -> Result<Box<dyn AsrEngine>, String> {
+    let uses_model = settings.backend != AsrBackend::Mock;
+    if uses_model && settings.is_english_only() && settings.language != "en" {
+        eprintln!(
+            "{} is an English-only model, language '{}' needs a multilingual model",
+            settings.model, settings.language
+        );
     }
-    if reader.spec().sample_format != SampleFormat::Int {
-        panic!("expected integer sample format");
+    match settings.backend {
+        AsrBackend::WhisperRs => Ok(Box::new(WhisperRsEngine::new(settings))),
+        AsrBackend::WhisperCpp => Ok(Box::new(WhisperCppEngine::new(settings))),
+        AsrBackend::Mock => {
+            let Some(mock_file) = &settings.mock_file else {
+                return Err("the mock ASR backend needs asr_mock_file, a subtitle file".to_string());
+            };
+            Ok(Box::new(MockEngine::from_file(
+                Path::new(mock_file),
+                &settings.language,
+            )?))
+        }
     }
-    if reader.spec().sample_rate != 16000 {
-        panic!("expected 16KHz sample rate");
+}
+
+pub struct WhisperRsEngine {
+    settings: AsrSettings,
+    language: String,
+}
+
+impl WhisperRsEngine {
+    pub fn new(settings: &AsrSettings) -> Self {
+        return Self {
+            settings: settings.clone(),
+            language: settings.language.clone(),
+        };
+    }
+}
+
+impl AsrEngine for WhisperRsEngine {
+    fn transcribe(&mut self, audio_path: &Path) -> Result<Vec<Segment>, String> {
+        let original_samples = parse_wav_file(audio_path)?;
+        let samples = whisper_rs::convert_integer_to_float_audio(&original_samples);
+        let mut ctx = WhisperContext::new(&self.settings.model)
+            .map_err(|e| format!("failed to open model {}: {:?}", self.settings.model, e))?;
+        self.language = match self.settings.language.as_str() {
+            AUTO_LANGUAGE => detect_language(&mut ctx, &samples),
+            language => language.to_string(),
+        };
+        // whisper continues from the prompt's tokens, they have to outlive `full`
+        let prompt_tokens = match self.settings.prompt.is_empty() {
+            true => Vec::new(),
+            false => ctx
+                .tokenize(&self.settings.prompt, MAX_PROMPT_TOKENS)
+                .map_err(|e| format!("failed to tokenize the prompt: {:?}", e))?,
+        };
+        let mut params = FullParams::new(SamplingStrategy::default());
+        params.set_n_threads(NUM_THREADS);
+        params.set_language(Some(&self.language));
+        params.set_translate(false);
+        params.set_print_special(false);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_token_timestamps(true);
+        if !prompt_tokens.is_empty() {
+            params.set_tokens(&prompt_tokens);
+        }
+        ctx.full(params, &samples)
+            .map_err(|e| format!("failed to transcribe: {:?}", e))?;
+
+        let mut segments = Vec::new();
+        for i in 0..ctx.full_n_segments() {
+            let text = ctx
+                .full_get_segment_text(i)
+                .map_err(|e| format!("failed to get segment {}: {:?}", i, e))?;
+            let mut tokens = Vec::new();
+            for j in 0..ctx.full_n_tokens(i) {
+                let text = ctx
+                    .full_get_token_text(i, j)
+                    .map_err(|e| format!("failed to get token {} of segment {}: {:?}", j, i, e))?;
+                if is_special(&text) {
+                    continue;
+                }
+                let data = ctx.full_get_token_data(i, j);
+                // whisper timestamps are in units of 10ms
+                tokens.push(Token {
+                    text,
+                    start: data.t0.max(0) as u64 * 10,
+                    end: data.t1.max(0) as u64 * 10,
+                    prob: data.p,
+                });
+            }
+            segments.push(Segment {
+                start: ctx.full_get_segment_t0(i).max(0) as u64 * 10,
+                end: ctx.full_get_segment_t1(i).max(0) as u64 * 10,
+                text,
+                tokens,
+            });
+        }
+        return Ok(segments);
     }
-    if reader.spec().bits_per_sample != 16 {
-        panic!("expected 16 bits per sample");
+    fn language(&self) -> String {
+        return self.language.clone();
     }
+}
 
+/// control tokens like `[_BEG_]` or `<|endoftext|>`, not part of the text
+fn is_special(token: &str) -> bool {
+    let token = token.trim();
+    return token.starts_with("[_") || token.starts_with("<|");
+}
+
+/// detects the spoken language from the first 30 seconds, English if detection fails
+fn detect_language(ctx: &mut WhisperContext, samples: &[f32]) -> String {
+    let probabilities = ctx
+        .pcm_to_mel(samples, NUM_THREADS as usize)
+        .and_then(|_| ctx.lang_detect(0, NUM_THREADS as usize));
+    let language = probabilities
+        .ok()
+        .and_then(|probabilities| most_likely(&probabilities))
+        .and_then(|id| whisper_rs::get_lang_str(id as i32));
+    match language {
+        Some(language) => {
+            println!("Detected spoken language: {}", language);
+            return language.to_string();
+        }
+        None => {
+            eprintln!("Failed to detect the spoken language, using en");
+            return "en".to_string();
+        }
+    }
+}
+/// index of the highest probability
+pub fn most_likely(probabilities: &[f32]) -> Option<usize> {
+    return probabilities
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.is_finite())
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(id, _)| id);
+}
+fn parse_wav_file(path: &Path) -> Result<Vec<i16>, String> {
+    let reader = WavReader::open(path).map_err(|e| format!("failed to read {:?}: {}", path, e))?;
+    let spec = reader.spec();
+    if spec.channels != 1 {
+        return Err("expected mono audio file".to_string());
+    }
+    if spec.sample_format != SampleFormat::Int {
+        return Err("expected integer sample format".to_string());
+    }
+    if spec.sample_rate != 16000 {
+        return Err("expected 16KHz sample rate".to_string());
+    }
+    if spec.bits_per_sample != 16 {
+        return Err("expected 16 bits per sample".to_string());
+    }
     return reader
         .into_samples::<i16>()
-        .map(|x| x.expect("sample"))
-        .collect::<Vec<_>>();
-}
-
-#[allow(unused)]
-fn whisper_cpp(audio_path: &Path, whisper_path: &Path, model_path: &Path) {
-    let output = Command::new(whisper_path.join("main"))
-        .args([
-            audio_path.to_str().unwrap(),
-            "-ml",
-            "1",
-            "--model",
-            model_path.to_str().unwrap(),
-            "--threads",
-            &7.to_string(),
-            "-l",
-            "en",
-            "-oj",
-        ])
-        .output()
-        .expect("failed to execute process");
-    println!("status: {}", output.status);
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+}
+
+/// runs the whisper.cpp executable and reads the JSON transcript it writes next to the audio
+pub struct WhisperCppEngine {
+    settings: AsrSettings,
+    language: String,
+}
+
+impl WhisperCppEngine {
+    pub fn new(settings: &AsrSettings) -> Self {
+        return Self {
+            settings: settings.clone(),
+            language: settings.language.clone(),
+        };
+    }
+}
+
+impl AsrEngine for WhisperCppEngine {
+    fn transcribe(&mut self, audio_path: &Path) -> Result<Vec<Segment>, String> {
+        let output_path = audio_path.with_extension("");
+        let threads = NUM_THREADS.to_string();
+        let mut args = vec![
+            audio_path.to_string_lossy().into_owned(),
+            "--model".to_string(),
+            self.settings.model.clone(),
+            "--threads".to_string(),
+            threads,
+            "-l".to_string(),
+            self.settings.language.clone(),
+            // the full output, with timed tokens
+            "-ojf".to_string(),
+            "-of".to_string(),
+            output_path.to_string_lossy().into_owned(),
+        ];
+        if !self.settings.prompt.is_empty() {
+            args.extend(["--prompt".to_string(), self.settings.prompt.clone()]);
+        }
+        let executable = Path::new(&self.settings.whisper_cpp).join("main");
+        let output = Command::new(&executable)
+            .args(&args)
+            .output()
+            .map_err(|e| format!("failed to run {:?}: {}", executable, e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+        let json = fs::read_to_string(output_path.with_extension("json"))
+            .map_err(|e| format!("failed to read the whisper.cpp transcript: {}", e))?;
+        let (segments, language) = parse_whisper_json(&json)?;
+        self.language = language.unwrap_or_else(|| self.settings.language.clone());
+        return Ok(segments);
+    }
+    fn language(&self) -> String {
+        return self.language.clone();
+    }
+}
+
+/// reads the transcript whisper.cpp writes with `-ojf`, and the detected language if it has one.
+/// tokens are only there in this full output, not with `-oj`
+pub fn parse_whisper_json(json: &str) -> Result<(Vec<Segment>, Option<String>), String> {
+    let json: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let offsets = |item: &Value| {
+        let offset = |key: &str| item["offsets"][key].as_u64();
+        Some((offset("from")?, offset("to")?))
+    };
+    let mut segments = Vec::new();
+    for item in json["transcription"].as_array().into_iter().flatten() {
+        let (start, end) = offsets(item).ok_or("whisper.cpp segment without offsets")?;
+        let tokens = item["tokens"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|token| {
+                let text = token["text"].as_str()?;
+                let (start, end) = offsets(token)?;
+                Some(Token {
+                    text: text.to_string(),
+                    start,
+                    end,
+                    prob: token["p"].as_f64().unwrap_or(1.0) as f32,
+                })
+            })
+            .filter(|token| !is_special(&token.text))
+            .collect();
+        segments.push(Segment {
+            start,
+            end,
+            text: item["text"].as_str().unwrap_or_default().to_string(),
+            tokens,
+        });
+    }
+    let language = json["result"]["language"].as_str().map(str::to_string);
+    return Ok((segments, language));
+}
+
+/// returns the same segments every time, to run the pipeline without a model.
+/// made from a subtitle file it has one token per word, timed across its cue
+pub struct MockEngine {
+    segments: Vec<Segment>,
+    language: String,
+}
+
+impl MockEngine {
+    pub fn new(segments: Vec<Segment>, language: &str) -> Self {
+        return Self {
+            segments,
+            language: language.to_string(),
+        };
+    }
+    pub fn from_file(path: &Path, language: &str) -> Result<Self, String> {
+        let cues = subtitles::parse_file(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let segments = cues
+            .iter()
+            .map(|cue| Segment {
+                start: cue.start,
+                end: cue.end,
+                text: cue.text.clone(),
+                tokens: subtitles::split_cues(std::slice::from_ref(cue), 0, true)
+                    .into_iter()
+                    .map(|word| Token {
+                        text: word.text,
+                        start: word.start,
+                        end: word.end,
+                        prob: 1.0,
+                    })
+                    .collect(),
+            })
+            .collect();
+        return Ok(Self::new(segments, language));
+    }
+}
+
+impl AsrEngine for MockEngine {
+    fn transcribe(&mut self, _audio_path: &Path) -> Result<Vec<Segment>, String> {
+        return Ok(self.segments.clone());
+    }
+    fn language(&self) -> String {
+        return self.language.clone();
+    }
 }
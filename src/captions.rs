@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use crate::eval;
+use crate::ocr::OcrResult;
+use crate::subtitles::Cue;
+
+/// words read with less confidence are left out, mostly noise from the picture behind the text
+const MIN_CONFIDENCE: f32 = 50.0;
+/// readings of consecutive frames at least this similar are the same caption
+const SAME_CAPTION_SIMILARITY: f32 = 0.7;
+
+/// the caption read from a frame's caption band, one line per line on screen,
+/// empty if there is no caption
+pub fn caption_text(result: &OcrResult) -> String {
+    return result
+        .lines()
+        .iter()
+        .map(|line| {
+            line.iter()
+                .filter(|word| word.confidence >= MIN_CONFIDENCE)
+                .map(|word| word.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|line| line.chars().filter(|c| c.is_alphanumeric()).count() >= 2)
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+/// turns the captions read from frames sampled every `frame_gap` ms, as (time in ms, text)
+/// in time order, into cues. consecutive similar readings are one caption shown from its
+/// first frame until the next caption or a frame without one, its text is the reading
+/// seen most often, so a frame misread here and there doesn't split or garble it
+pub fn stabilize(frames: &[(u64, String)], frame_gap: u64) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    let mut run: Vec<&str> = Vec::new();
+    let mut start = 0;
+    for (time, text) in frames {
+        if let Some(last) = run.last() {
+            if text.is_empty() || !same_caption(last, text) {
+                cues.push(cue(start, *time, &run));
+                run.clear();
+            }
+        }
+        if !text.is_empty() {
+            if run.is_empty() {
+                start = *time;
+            }
+            run.push(text);
+        }
+    }
+    if let Some((last, _)) = frames.last().filter(|_| !run.is_empty()) {
+        cues.push(cue(start, last + frame_gap, &run));
+    }
+    return cues;
+
+    fn same_caption(a: &str, b: &str) -> bool {
+        return eval::text_similarity(&a.to_lowercase(), &b.to_lowercase())
+            >= SAME_CAPTION_SIMILARITY;
+    }
+    fn cue(start: u64, end: u64, readings: &[&str]) -> Cue {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for reading in readings {
+            *counts.entry(reading).or_default() += 1;
+        }
+        // the most frequent reading, the longest of those on a tie
+        let text = counts
+            .into_iter()
+            .max_by_key(|(reading, count)| (*count, reading.len(), *reading))
+            .map(|(reading, _)| reading.to_string())
+            .unwrap_or_default();
+        return Cue { start, end, text };
+    }
+}
@@ -1,3 +1,290 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::analyzer::Analyzer;
+use crate::asr::AsrSettings;
+use crate::ensemble::{self, Member};
+use crate::indexer::OverlayMode;
+use crate::indexer::Region;
+use crate::mask::{self, Area, Mask};
+use crate::ocr::{Backend, OcrSettings};
+use crate::preprocessing::Pipeline;
+use crate::vidsplicer::ffmpeg_utils::Sampling;
+
 pub mod constants {
     pub const NUM_THREADS: i32 = 7;
+    pub const DEFAULT_PORT: u16 = 8080;
+    pub const CONFIG_PATH: &str = "vcf.toml";
+}
+
+/// options that take no value on the command line, `--flag` is the same as `--flag true`
+const SWITCHES: &[&str] = &[
+    "subtitle_word_level",
+    "skip_asr_with_subtitles",
+    "debug_boxes",
+    "frames_in_memory",
+    "detect_text",
+    "slide_diff",
+    "captions",
+    "refine",
+    "gec",
+    "spell_correct",
+];
+
+/// Runtime options, read from `vcf.toml` (flat `key = value` lines)
+/// and overridden by `--key value` flags on the command line.
+/// Lines under `[profile.<name>]` only apply with `--profile <name>`,
+/// lines under `[video.<name>]` only when indexing a video with that file name (without extension).
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// port used by `vcf serve`
+    pub port: u16,
+    /// longest subtitle line written to the transcript, 0 for no limit
+    pub subtitle_max_line: usize,
+    /// write one subtitle cue per word
+    pub subtitle_word_level: bool,
+    /// don't run whisper when the video has subtitle streams or sidecar files
+    pub skip_asr_with_subtitles: bool,
+    /// how frames are picked for OCR, see `Sampling`
+    pub sampling: Sampling,
+    /// decode frames from an ffmpeg pipe instead of extracting them to `dump/<video>/frames`
+    pub frames_in_memory: bool,
+    /// `code` also indexes compound identifiers, their parts and operators,
+    /// and reads frames with symbols and indentation kept
+    pub analyzer: Analyzer,
+    pub ocr_backend: Backend,
+    pub ocr: OcrSettings,
+    /// read every frame with these configurations and vote on the words, e.g.
+    /// `leptess:3:best,tesseract:12:fast`, or `default`, see `ensemble::vote`
+    pub ocr_ensemble: Vec<Member>,
+    /// areas of interest and excluded areas, set with `roi` and `exclude`
+    pub mask: Mask,
+    /// section of the config file applied on top of the top-level options
+    pub profile: Option<String>,
+    /// steps applied to every frame before OCR, e.g. `gray,upscale,otsu,invert`
+    pub preprocess: Pipeline,
+    /// skip frames without edges that look like text, and only recognize the text regions
+    pub detect_text: bool,
+    /// consecutive frames at least this similar (dHash, 0 to 1) are OCRed once, 0 keeps every frame
+    pub dedup_threshold: f32,
+    /// also read burned-in captions from `caption_band` into `captions.srt`, see `captions::stabilize`
+    pub captions: bool,
+    /// the part of the frame captions are read from, kept out of the frame text
+    pub caption_band: Area,
+    /// captions change quickly, so their frames are sampled densely
+    pub caption_sampling: Sampling,
+    /// whisper model and spoken language
+    pub asr: AsrSettings,
+    /// index a frame's lines only when they first appear, see `Indexer::record_ocr_diff`
+    pub slide_diff: bool,
+    /// words in more than this share of the frames are static overlays, 0 to keep them as they are
+    pub overlay_share: f32,
+    /// words at the same spot in more than this share of the frames are static overlays too
+    pub overlay_fixed_share: f32,
+    /// whether overlay words are ranked last or not indexed at all
+    pub overlay_mode: OverlayMode,
+    /// also index the grammar-corrected form of the OCR and ASR text, see `gec::correct_all`.
+    /// frames read as code are left as they are
+    pub gec: bool,
+    /// snap misread OCR words to words said around their frame or in `word_list`,
+    /// see `Indexer::correct_from_speech`
+    pub spell_correct: bool,
+    /// how many seconds before or after a frame a word may be said to correct it
+    pub spell_window: u64,
+    /// file of words OCR is corrected to wherever they are misread, one per line, `#` for comments
+    pub word_list: Option<PathBuf>,
+    /// file of project terms both engines are biased towards, see `Glossary`
+    pub glossary: Option<PathBuf>,
+    /// find the frames the first search hits came on and left the screen at, see `Refiner`
+    pub refine: bool,
+    /// save every frame with its OCR word boxes drawn to `dump/<video>/debug`
+    pub debug_boxes: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        return Self {
+            port: constants::DEFAULT_PORT,
+            subtitle_max_line: 42,
+            subtitle_word_level: false,
+            skip_asr_with_subtitles: false,
+            sampling: Sampling::Keyframes,
+            frames_in_memory: false,
+            analyzer: Analyzer::Text,
+            ocr_backend: Backend::LepTess,
+            ocr: OcrSettings::default(),
+            ocr_ensemble: Vec::new(),
+            mask: Mask::default(),
+            profile: None,
+            preprocess: Pipeline::default(),
+            detect_text: false,
+            dedup_threshold: 0.95,
+            captions: false,
+            caption_band: Area::Relative(Region {
+                x: 0.0,
+                y: 0.75,
+                w: 1.0,
+                h: 0.25,
+            }),
+            caption_sampling: Sampling::Interval(0.5),
+            asr: AsrSettings::default(),
+            slide_diff: false,
+            overlay_share: 0.0,
+            overlay_fixed_share: 0.0,
+            overlay_mode: OverlayMode::Downweight,
+            gec: false,
+            spell_correct: false,
+            spell_window: 30,
+            word_list: None,
+            glossary: None,
+            refine: false,
+            debug_boxes: false,
+        };
+    }
+}
+
+impl Options {
+    /// returns the parsed options and the remaining positional arguments
+    pub fn parse(args: &[String]) -> Result<(Self, Vec<String>), String> {
+        let contents = fs::read_to_string(constants::CONFIG_PATH).unwrap_or_default();
+        return Self::parse_with(&contents, args);
+    }
+
+    /// like `parse`, with `contents` as the config file
+    pub fn parse_with(contents: &str, args: &[String]) -> Result<(Self, Vec<String>), String> {
+        let mut flags = Vec::new();
+        let mut positional = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                positional.push(arg.clone());
+                continue;
+            };
+            match flag.split_once('=') {
+                Some((key, value)) => flags.push((key.replace('-', "_"), value.to_string())),
+                None => {
+                    let key = flag.replace('-', "_");
+                    if SWITCHES.contains(&key.as_str()) {
+                        flags.push((key, "true".to_string()));
+                    } else {
+                        let value = args
+                            .next()
+                            .ok_or_else(|| format!("missing value for --{}", flag))?;
+                        flags.push((key, value.clone()));
+                    }
+                }
+            }
+        }
+        let mut options = Options::default();
+        options.apply_file(contents)?;
+        // the sections have to be applied before the flags, which override them
+        if let Some((_, profile)) = flags.iter().rev().find(|(key, _)| key == "profile") {
+            options.profile = Some(profile.clone());
+        }
+        if let Some(profile) = options.profile.clone() {
+            if !options.apply_section(contents, &format!("profile.{}", profile))? {
+                return Err(format!("unknown profile '{}'", profile));
+            }
+        }
+        for arg in &positional {
+            if let Some(name) = Path::new(arg).file_stem().and_then(|name| name.to_str()) {
+                options.apply_section(contents, &format!("video.{}", name))?;
+            }
+        }
+        for (key, value) in flags {
+            options.set(&key, &value)?;
+        }
+        return Ok((options, positional));
+    }
+
+    /// applies every top-level `key = value` line of a config file, `#` starts a comment
+    pub fn apply_file(&mut self, contents: &str) -> Result<(), String> {
+        self.apply_lines(contents, None)?;
+        return Ok(());
+    }
+
+    /// applies the lines under `[section]`, returns false if there is no such section
+    pub fn apply_section(&mut self, contents: &str, section: &str) -> Result<bool, String> {
+        return self.apply_lines(contents, Some(section));
+    }
+
+    fn apply_lines(&mut self, contents: &str, section: Option<&str>) -> Result<bool, String> {
+        let mut current = None;
+        let mut found = section.is_none();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = Some(name.trim().trim_matches('"'));
+                found |= current == section;
+                continue;
+            }
+            if current != section {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("invalid config line '{}'", line))?;
+            self.set(key.trim(), value.trim().trim_matches('"'))?;
+        }
+        return Ok(found);
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "port" => self.port = parse_value(key, value)?,
+            "subtitle_max_line" => self.subtitle_max_line = parse_value(key, value)?,
+            "subtitle_word_level" => self.subtitle_word_level = parse_value(key, value)?,
+            "skip_asr_with_subtitles" => self.skip_asr_with_subtitles = parse_value(key, value)?,
+            "sampling" => self.sampling = value.parse()?,
+            "frames_in_memory" => self.frames_in_memory = parse_value(key, value)?,
+            "analyzer" => {
+                self.analyzer = value.parse()?;
+                self.ocr.code = self.analyzer == Analyzer::Code;
+            }
+            "ocr_backend" => self.ocr_backend = value.parse()?,
+            "ocr_lang" => self.ocr.lang = value.to_string(),
+            "ocr_psm" => self.ocr.psm = parse_value(key, value)?,
+            "ocr_oem" => self.ocr.oem = parse_value(key, value)?,
+            "ocr_dpi" => self.ocr.dpi = parse_value(key, value)?,
+            "ocr_ensemble" => self.ocr_ensemble = ensemble::parse_members(value)?,
+            "tessdata_dir" => self.ocr.tessdata_dir = Some(value.to_string()),
+            "roi" => self.mask.include = mask::parse_areas(value)?,
+            "exclude" => self.mask.exclude = mask::parse_areas(value)?,
+            "profile" => self.profile = Some(value.to_string()),
+            "preprocess" => self.preprocess = value.parse()?,
+            "detect_text" => self.detect_text = parse_value(key, value)?,
+            "dedup_threshold" => self.dedup_threshold = parse_value(key, value)?,
+            "captions" => self.captions = parse_value(key, value)?,
+            "caption_band" => self.caption_band = value.parse()?,
+            "caption_sampling" => self.caption_sampling = value.parse()?,
+            "asr_model" => self.asr.model = value.to_string(),
+            "asr_lang" => self.asr.language = value.to_string(),
+            "asr_backend" => self.asr.backend = value.parse()?,
+            "whisper_cpp" => self.asr.whisper_cpp = value.to_string(),
+            "asr_mock_file" => self.asr.mock_file = Some(value.to_string()),
+            "slide_diff" => self.slide_diff = parse_value(key, value)?,
+            "overlay_share" => self.overlay_share = parse_value(key, value)?,
+            "overlay_fixed_share" => self.overlay_fixed_share = parse_value(key, value)?,
+            "overlay_mode" => self.overlay_mode = value.parse()?,
+            "gec" => self.gec = parse_value(key, value)?,
+            "spell_correct" => self.spell_correct = parse_value(key, value)?,
+            "spell_window" => self.spell_window = parse_value(key, value)?,
+            "word_list" => self.word_list = Some(PathBuf::from(value)),
+            "glossary" => self.glossary = Some(PathBuf::from(value)),
+            "refine" => self.refine = parse_value(key, value)?,
+            "debug_boxes" => self.debug_boxes = parse_value(key, value)?,
+            _ => return Err(format!("unknown option '{}'", key)),
+        }
+        return Ok(());
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    return value
+        .parse::<T>()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, key));
 }
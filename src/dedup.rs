@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use image::{imageops, DynamicImage};
+
+/// a kept frame and the time it stays on screen, `end` is the time of its last near-duplicate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shot<T = PathBuf> {
+    pub frame: T,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// difference hash: one bit per horizontally adjacent pair of a 9x8 grayscale thumbnail
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let thumbnail = imageops::resize(&image.to_luma8(), 9, 8, imageops::FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = thumbnail.get_pixel(x + 1, y)[0] > thumbnail.get_pixel(x, y)[0];
+            hash = (hash << 1) | brighter as u64;
+        }
+    }
+    return hash;
+}
+
+/// share of equal bits between two hashes, 1.0 for identical frames
+pub fn similarity(a: u64, b: u64) -> f32 {
+    return 1.0 - (a ^ b).count_ones() as f32 / 64.0;
+}
+
+/// folds runs of near-duplicate frames into the first frame of the run as the frames arrive.
+/// a frame is compared to the last kept one so a slow fade can't creep through,
+/// a threshold of 0 keeps every frame.
+pub struct Folder<T> {
+    threshold: f32,
+    last_hash: u64,
+    shot: Option<Shot<T>>,
+    pub seen: usize,
+    pub kept: usize,
+}
+
+impl<T> Folder<T> {
+    pub fn new(threshold: f32) -> Self {
+        return Self {
+            threshold,
+            last_hash: 0,
+            shot: None,
+            seen: 0,
+            kept: 0,
+        };
+    }
+    /// adds the next frame in time order, returns the previous shot once it is complete
+    pub fn push(&mut self, hash: u64, frame: T, timestamp: u64) -> Option<Shot<T>> {
+        self.seen += 1;
+        if let Some(shot) = self.shot.as_mut() {
+            if self.threshold > 0.0 && similarity(self.last_hash, hash) >= self.threshold {
+                shot.end = timestamp;
+                return None;
+            }
+        }
+        self.last_hash = hash;
+        self.kept += 1;
+        return self.shot.replace(Shot {
+            frame,
+            start: timestamp,
+            end: timestamp,
+        });
+    }
+    /// the last shot, once there are no more frames
+    pub fn finish(&mut self) -> Option<Shot<T>> {
+        return self.shot.take();
+    }
+    pub fn skipped(&self) -> usize {
+        return self.seen - self.kept;
+    }
+}
+
+/// folds `frames`, given as (hash, frame, timestamp) in time order,
+/// returns the shots and how many frames were skipped as near-duplicates
+pub fn fold<T>(frames: Vec<(u64, T, u64)>, threshold: f32) -> (Vec<Shot<T>>, usize) {
+    let mut folder = Folder::new(threshold);
+    let mut shots: Vec<Shot<T>> = frames
+        .into_iter()
+        .filter_map(|(hash, frame, timestamp)| folder.push(hash, frame, timestamp))
+        .collect();
+    shots.extend(folder.finish());
+    return (shots, folder.skipped());
+}
@@ -3,6 +3,8 @@ use std::path::Path;
 
 use serde_json::{to_string_pretty, Value};
 
+use crate::indexer::{Correction, Indexer};
+
 pub fn create_dump(dump_dir: &Path) {
     if dump_dir.exists() {
         fs::remove_dir_all(dump_dir).expect("Failed to delete directories");
@@ -14,3 +16,42 @@ pub fn save_as_json(index: Value, path: &Path) -> std::io::Result<()> {
     fs::write(path, to_string_pretty(&index)?)?;
     Ok(())
 }
+
+/// loads an index previously written by `save_as_json`
+pub fn load_index(path: &Path) -> std::io::Result<Indexer> {
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// writes every correction as a tab separated line: time, read word, corrected word,
+/// edits, and when the word was said or `list` for the word list
+pub fn save_corrections(corrections: &[Correction], path: &Path) -> std::io::Result<()> {
+    let mut tsv = String::from("time\tread\tcorrected\tedits\tsource\n");
+    for correction in corrections {
+        let source = match correction.spoken_at {
+            Some(spoken_at) => spoken_at.to_string(),
+            None => "list".to_string(),
+        };
+        tsv.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            correction.timestamp,
+            correction.read,
+            correction.corrected,
+            correction.distance,
+            source
+        ));
+    }
+    fs::write(path, tsv)?;
+    Ok(())
+}
+
+/// the words of a word list file, one per line, `#` starts a comment
+pub fn load_word_list(path: &Path) -> std::io::Result<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
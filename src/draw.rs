@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::mask::Mask;
+use crate::ocr::{BoundingBox, OcrResult};
+
+const BOX_COLOR: Rgb<u8> = Rgb([255, 0, 0]);
+const INCLUDE_COLOR: Rgb<u8> = Rgb([0, 200, 0]);
+
+/// draws the outline of `bbox`, clipped to the image
+pub fn draw_rect(image: &mut RgbImage, bbox: &BoundingBox, color: Rgb<u8>, thickness: u32) {
+    let (width, height) = image.dimensions();
+    if bbox.width == 0 || bbox.height == 0 || bbox.left >= width || bbox.top >= height {
+        return;
+    }
+    let right = (bbox.left + bbox.width).min(width) - 1;
+    let bottom = (bbox.top + bbox.height).min(height) - 1;
+    for t in 0..thickness {
+        for x in bbox.left..=right {
+            image.put_pixel(x, (bbox.top + t).min(bottom), color);
+            image.put_pixel(x, bottom.saturating_sub(t).max(bbox.top), color);
+        }
+        for y in bbox.top..=bottom {
+            image.put_pixel((bbox.left + t).min(right), y, color);
+            image.put_pixel(right.saturating_sub(t).max(bbox.left), y, color);
+        }
+    }
+}
+
+/// a copy of the frame with every recognized word boxed
+pub fn draw_boxes(frame: &DynamicImage, result: &OcrResult) -> RgbImage {
+    let mut image = frame.to_rgb8();
+    for word in &result.words {
+        draw_rect(&mut image, &word.bbox, BOX_COLOR, 2);
+    }
+    return image;
+}
+
+/// saves a copy of the frame file with every recognized word boxed
+pub fn save_boxes(
+    frame_path: &Path,
+    result: &OcrResult,
+    output_path: &Path,
+) -> image::ImageResult<()> {
+    return draw_boxes(&image::open(frame_path)?, result).save(output_path);
+}
+
+/// a copy of the frame with the areas of interest outlined and the excluded areas shaded
+pub fn draw_mask(frame: &DynamicImage, mask: &Mask) -> RgbImage {
+    let mut image = frame.to_rgb8();
+    let (width, height) = image.dimensions();
+    for area in &mask.exclude {
+        let bbox = area.to_bbox(width, height);
+        for y in bbox.top..bbox.top + bbox.height {
+            for x in bbox.left..bbox.left + bbox.width {
+                let pixel = image.get_pixel_mut(x, y);
+                for (c, shade) in pixel.0.iter_mut().zip(BOX_COLOR.0) {
+                    *c = ((*c as u16 + shade as u16) / 2) as u8;
+                }
+            }
+        }
+        draw_rect(&mut image, &bbox, BOX_COLOR, 3);
+    }
+    for area in &mask.include {
+        draw_rect(&mut image, &area.to_bbox(width, height), INCLUDE_COLOR, 3);
+    }
+    return image;
+}
@@ -0,0 +1,304 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use image::DynamicImage;
+
+use crate::config::Options;
+use crate::eval;
+use crate::ocr::{self, Backend, OcrEngine, OcrResult, OcrSettings, OcrWord};
+
+/// one configuration of each binding, with both page segmentation modes and both model sizes
+pub const DEFAULT_MEMBERS: &str = "leptess:3:best,tesseract:12:best,tesseract-cli:3:fast";
+/// how much the number of members that read a word counts against their confidence in it
+const VOTE_WEIGHT: f32 = 0.5;
+/// confidence given to members that read nothing where others read a word,
+/// lower lets fewer members keep a word
+const NULL_CONFIDENCE: f32 = 0.3;
+
+/// an OCR configuration taking part in an ensemble, written `backend:psm:model`,
+/// the model is `best`, `fast` or a tessdata directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub backend: Backend,
+    pub psm: u32,
+    pub model: String,
+}
+
+impl FromStr for Member {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let parts: Vec<&str> = s.splitn(3, ':').collect();
+        let [backend, psm, model] = parts[..] else {
+            return Err(format!(
+                "invalid ensemble member '{}', expected backend:psm:model",
+                s
+            ));
+        };
+        return Ok(Self {
+            backend: backend.parse()?,
+            psm: psm
+                .parse()
+                .map_err(|_| format!("invalid page segmentation mode '{}'", psm))?,
+            model: model.to_string(),
+        });
+    }
+}
+
+impl fmt::Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}:{}:{}", self.backend.name(), self.psm, self.model);
+    }
+}
+
+impl Member {
+    /// `settings` with this member's page segmentation mode and model
+    pub fn settings(&self, settings: &OcrSettings) -> OcrSettings {
+        let tessdata_dir = match self.model.as_str() {
+            "best" | "fast" => format!("models/traineddata/tessdata_{}", self.model),
+            path => path.to_string(),
+        };
+        return OcrSettings {
+            psm: self.psm,
+            tessdata_dir: Some(tessdata_dir),
+            ..settings.clone()
+        };
+    }
+}
+
+/// parses members separated by `,`, `default` for `DEFAULT_MEMBERS`
+pub fn parse_members(s: &str) -> Result<Vec<Member>, String> {
+    let s = match s.trim() {
+        "default" => DEFAULT_MEMBERS,
+        s => s,
+    };
+    return s
+        .split(',')
+        .filter(|member| !member.trim().is_empty())
+        .map(str::parse)
+        .collect();
+}
+
+/// reads every frame with several configurations and votes on each word, see `vote`
+pub struct Ensemble {
+    engines: Vec<Box<dyn OcrEngine>>,
+}
+
+impl Ensemble {
+    pub fn new(members: &[Member], settings: &OcrSettings) -> Result<Self, String> {
+        let engines = members
+            .iter()
+            .map(|member| ocr::make_engine(member.backend, &member.settings(settings)))
+            .collect::<Result<_, _>>()?;
+        return Ok(Self { engines });
+    }
+}
+
+impl OcrEngine for Ensemble {
+    fn recognize(&mut self, path: &Path) -> Result<OcrResult, String> {
+        let results = self
+            .engines
+            .iter_mut()
+            .map(|engine| engine.recognize(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(vote(&results));
+    }
+    fn recognize_image(&mut self, image: &DynamicImage) -> Result<OcrResult, String> {
+        let results = self
+            .engines
+            .iter_mut()
+            .map(|engine| engine.recognize_image(image))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(vote(&results));
+    }
+}
+
+/// the ensemble set with `ocr_ensemble`, or the single engine of `ocr_backend`
+pub fn make_engine(options: &Options) -> Result<Box<dyn OcrEngine>, String> {
+    if options.ocr_ensemble.is_empty() {
+        return ocr::make_engine(options.ocr_backend, &options.ocr);
+    }
+    return Ok(Box::new(Ensemble::new(
+        &options.ocr_ensemble,
+        &options.ocr,
+    )?));
+}
+
+/// a place in the aligned readings, the words the members read there
+struct Slot {
+    /// where the word goes in the layout
+    place: OcrWord,
+    readings: Vec<OcrWord>,
+}
+
+/// combines the readings of one frame by several members, ROVER-style: the words of every
+/// reading are aligned to the most confident one, then at each place the word with the best
+/// mix of votes and mean confidence wins, or nothing if most members read nothing there.
+/// the layout is the most confident reading's
+pub fn vote(results: &[OcrResult]) -> OcrResult {
+    let total = |result: &OcrResult| result.words.iter().map(|w| w.confidence).sum::<f32>();
+    let Some(backbone) =
+        (0..results.len()).max_by(|&a, &b| total(&results[a]).total_cmp(&total(&results[b])))
+    else {
+        return OcrResult::default();
+    };
+    let mut slots: Vec<Slot> = results[backbone]
+        .words
+        .iter()
+        .map(|word| Slot {
+            place: word.clone(),
+            readings: vec![word.clone()],
+        })
+        .collect();
+    let key = |word: &OcrWord| word.text.to_lowercase();
+    for (_, result) in results.iter().enumerate().filter(|(i, _)| *i != backbone) {
+        let keys: Vec<String> = slots.iter().map(|slot| key(&slot.place)).collect();
+        let words: Vec<String> = result.words.iter().map(key).collect();
+        let first = slots.first().map(|slot| slot.place.clone());
+        let mut old: Vec<Option<Slot>> = slots.into_iter().map(Some).collect();
+        slots = Vec::new();
+        let distance = |a: &String, b: &String| 1.0 - eval::text_similarity(a, b);
+        for pair in eval::align_with(&keys, &words, distance) {
+            match pair {
+                (Some(i), word) => {
+                    let Some(mut slot) = old[i].take() else {
+                        continue;
+                    };
+                    slot.readings.extend(word.map(|j| result.words[j].clone()));
+                    slots.push(slot);
+                }
+                // a word the earlier readings missed, on the line of the word before it
+                (None, Some(j)) => {
+                    let word = &result.words[j];
+                    let place = match slots.last().map(|slot| &slot.place).or(first.as_ref()) {
+                        Some(next_to) => OcrWord {
+                            block: next_to.block,
+                            paragraph: next_to.paragraph,
+                            line: next_to.line,
+                            ..word.clone()
+                        },
+                        None => word.clone(),
+                    };
+                    slots.push(Slot {
+                        place,
+                        readings: vec![word.clone()],
+                    });
+                }
+                (None, None) => (),
+            }
+        }
+    }
+    let words = slots
+        .iter()
+        .filter_map(|slot| decide(slot, results.len()))
+        .collect();
+    return OcrResult::from_words(words, results[backbone].width, results[backbone].height);
+
+    fn decide(slot: &Slot, members: usize) -> Option<OcrWord> {
+        let score = |votes: usize, confidence: f32| {
+            VOTE_WEIGHT * votes as f32 / members as f32 + (1.0 - VOTE_WEIGHT) * confidence
+        };
+        // readings that differ only in case are the same word
+        let mut words: Vec<Vec<&OcrWord>> = Vec::new();
+        for reading in &slot.readings {
+            let text = reading.text.to_lowercase();
+            match words.iter_mut().find(|w| w[0].text.to_lowercase() == text) {
+                Some(word) => word.push(reading),
+                None => words.push(vec![reading]),
+            }
+        }
+        let mean = |readings: &[&OcrWord]| {
+            readings.iter().map(|r| r.confidence).sum::<f32>() / readings.len() as f32
+        };
+        // the earliest on a tie, the backbone's if it is among them
+        let (best_score, readings) = words
+            .iter()
+            .map(|readings| (score(readings.len(), mean(readings) / 100.0), readings))
+            .rev()
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))?;
+        let missing = members.saturating_sub(slot.readings.len());
+        if missing > 0 && score(missing, NULL_CONFIDENCE) > best_score {
+            return None;
+        }
+        let spelling = readings
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))?;
+        return Some(OcrWord {
+            text: spelling.text.clone(),
+            confidence: mean(readings),
+            ..slot.place.clone()
+        });
+    }
+}
+
+/// mean error rates and time per frame of a configuration over a labelled frame set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    pub name: String,
+    pub cer: f32,
+    pub wer: f32,
+    pub ms_per_frame: f32,
+}
+
+/// reads every `<name>.png` in `dir` that has a `<name>.txt` transcript with each member,
+/// and scores the members and their vote, the vote's time includes all of its members
+pub fn evaluate(
+    dir: &Path,
+    members: &[Member],
+    settings: &OcrSettings,
+) -> Result<Vec<Score>, String> {
+    let mut frames: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "png") && path.with_extension("txt").exists()
+        })
+        .collect();
+    if frames.is_empty() {
+        return Err(format!("no labelled frames in {}", dir.display()));
+    }
+    frames.sort();
+    let mut engines = members
+        .iter()
+        .map(|member| ocr::make_engine(member.backend, &member.settings(settings)))
+        .collect::<Result<Vec<_>, _>>()?;
+    // error rates and time, per member and for the vote last
+    let mut totals = vec![(0.0, 0.0, Duration::ZERO); members.len() + 1];
+    for frame in &frames {
+        let reference =
+            fs::read_to_string(frame.with_extension("txt")).map_err(|e| e.to_string())?;
+        let mut results = Vec::new();
+        let mut ensemble_time = Duration::ZERO;
+        for (engine, total) in engines.iter_mut().zip(&mut totals) {
+            let start = Instant::now();
+            let result = engine.recognize(frame)?;
+            let elapsed = start.elapsed();
+            total.0 += eval::cer(&reference, &result.text);
+            total.1 += eval::wer(&reference, &result.text);
+            total.2 += elapsed;
+            ensemble_time += elapsed;
+            results.push(result);
+        }
+        let start = Instant::now();
+        let voted = vote(&results);
+        let total = &mut totals[members.len()];
+        total.0 += eval::cer(&reference, &voted.text);
+        total.1 += eval::wer(&reference, &voted.text);
+        total.2 += ensemble_time + start.elapsed();
+    }
+    let names = members.iter().map(Member::to_string);
+    let n = frames.len() as f32;
+    return Ok(names
+        .chain(["ensemble".to_string()])
+        .zip(totals)
+        .map(|(name, (cer, wer, time))| Score {
+            name,
+            cer: cer / n,
+            wer: wer / n,
+            ms_per_frame: time.as_secs_f32() * 1000.0 / n,
+        })
+        .collect());
+}
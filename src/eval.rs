@@ -0,0 +1,96 @@
+/// Levenshtein distance between two sequences, e.g. the chars or words of two texts
+pub fn edit_distance<T: PartialEq>(a: &[T], b: &[T]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, x) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != y);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    return previous[b.len()];
+}
+
+/// 1.0 for equal texts, 0.0 for texts with nothing in common, by characters
+pub fn text_similarity(a: &str, b: &str) -> f32 {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    return 1.0 - edit_distance(&a, &b) as f32 / longest as f32;
+}
+
+/// a cheapest edit script between `a` and `b`, as pairs of positions: both for a match or
+/// substitution, only one for a deletion from `a` or an insertion from `b`. substitutions cost
+/// from 0 to 1, so e.g. misread words pair up with the words they were misread from
+pub fn align_with<T>(
+    a: &[T],
+    b: &[T],
+    substitution: impl Fn(&T, &T) -> f32,
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut costs = vec![vec![0.0; b.len() + 1]; a.len() + 1];
+    // the pair each cell was reached with
+    let mut steps = vec![vec![(false, false); b.len() + 1]; a.len() + 1];
+    for i in 1..=a.len() {
+        costs[i][0] = i as f32;
+        steps[i][0] = (true, false);
+    }
+    for j in 1..=b.len() {
+        costs[0][j] = j as f32;
+        steps[0][j] = (false, true);
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let candidates = [
+                (
+                    costs[i - 1][j - 1] + substitution(&a[i - 1], &b[j - 1]),
+                    (true, true),
+                ),
+                (costs[i - 1][j] + 1.0, (true, false)),
+                (costs[i][j - 1] + 1.0, (false, true)),
+            ];
+            // the first of equal costs, so matches and substitutions come first
+            let (cost, step) = candidates
+                .into_iter()
+                .rev()
+                .min_by(|(x, _), (y, _)| x.total_cmp(y))
+                .unwrap_or((0.0, (true, true)));
+            costs[i][j] = cost;
+            steps[i][j] = step;
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 || j > 0 {
+        let (from_a, from_b) = steps[i][j];
+        pairs.push((from_a.then(|| i - 1), from_b.then(|| j - 1)));
+        i -= usize::from(from_a);
+        j -= usize::from(from_b);
+    }
+    pairs.reverse();
+    return pairs;
+}
+
+/// character error rate of `hypothesis` against the `reference` transcript,
+/// runs of whitespace count as one space
+pub fn cer(reference: &str, hypothesis: &str) -> f32 {
+    let normalize = |text: &str| -> Vec<char> {
+        text.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .collect()
+    };
+    let (reference, hypothesis) = (normalize(reference), normalize(hypothesis));
+    return edit_distance(&reference, &hypothesis) as f32 / reference.len().max(1) as f32;
+}
+
+/// word error rate of `hypothesis` against the `reference` transcript
+pub fn wer(reference: &str, hypothesis: &str) -> f32 {
+    let reference: Vec<&str> = reference.split_whitespace().collect();
+    let hypothesis: Vec<&str> = hypothesis.split_whitespace().collect();
+    return edit_distance(&reference, &hypothesis) as f32 / reference.len().max(1) as f32;
+}
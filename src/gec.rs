@@ -1,13 +1,53 @@
-#![allow(unused)]
+use std::sync::{Arc, Mutex, OnceLock};
 
 use nlprule::{Rules, Tokenizer};
-pub fn correct(text: &str) -> String {
-    let mut tokenizer_bytes: &'static [u8] = include_bytes!("../models/en_tokenizer.bin");
-    let mut rules_bytes: &'static [u8] = include_bytes!("../models/en_rules.bin");
+use threadpool::ThreadPool;
+
+/// the nlprule grammar model
+pub struct Corrector {
+    tokenizer: Tokenizer,
+    rules: Rules,
+}
+
+static CORRECTOR: OnceLock<Corrector> = OnceLock::new();
+
+impl Corrector {
+    fn load() -> Self {
+        let mut tokenizer_bytes: &'static [u8] = include_bytes!("../models/en_tokenizer.bin");
+        let mut rules_bytes: &'static [u8] = include_bytes!("../models/en_rules.bin");
+        return Self {
+            tokenizer: Tokenizer::from_reader(&mut tokenizer_bytes)
+                .expect("tokenizer binary is invalid"),
+            rules: Rules::from_reader(&mut rules_bytes).expect("rules binary is invalid"),
+        };
+    }
+    pub fn correct(&self, text: &str) -> String {
+        return self.rules.correct(text, &self.tokenizer);
+    }
+}
+
+/// the model, parsed on first use and shared by every thread after that
+pub fn corrector() -> &'static Corrector {
+    return CORRECTOR.get_or_init(Corrector::load);
+}
 
-    let tokenizer =
-        Tokenizer::from_reader(&mut tokenizer_bytes).expect("tokenizer binary is invalid");
-    let rules = Rules::from_reader(&mut rules_bytes).expect("rules binary is invalid");
+pub fn correct(text: &str) -> String {
+    return corrector().correct(text);
+}
 
-    return rules.correct(text, &tokenizer);
+/// corrects every text on `num_threads` threads, in the same order
+pub fn correct_all(texts: Vec<String>, num_threads: usize) -> Vec<String> {
+    // parsed once up front, not by every thread that gets there first
+    corrector();
+    let thread_pool = ThreadPool::new(num_threads.max(1));
+    let corrected = Arc::new(Mutex::new(vec![String::new(); texts.len()]));
+    for (i, text) in texts.into_iter().enumerate() {
+        let corrected = Arc::clone(&corrected);
+        thread_pool.execute(move || {
+            let text = correct(&text);
+            corrected.lock().unwrap()[i] = text;
+        });
+    }
+    thread_pool.join();
+    return std::mem::take(&mut *corrected.lock().unwrap());
 }
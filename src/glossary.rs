@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// whisper's prompt is cut to its last 224 tokens, this keeps it well inside
+const MAX_PROMPT_LENGTH: usize = 600;
+
+/// product names, acronyms and other terms of a project that the engines get wrong,
+/// one per line, lines starting with `#` are comments. lines with a `\` are tesseract patterns,
+/// e.g. `JIRA-\d\d\d\d`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Glossary {
+    pub terms: Vec<String>,
+    pub patterns: Vec<String>,
+}
+
+impl Glossary {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read glossary {}: {}", path.display(), e))?;
+        return Ok(Self::parse(&contents));
+    }
+    pub fn parse(contents: &str) -> Self {
+        let mut glossary = Self::default();
+        for line in contents.lines() {
+            match line.trim() {
+                line if line.is_empty() || line.starts_with('#') => (),
+                pattern if pattern.contains('\\') => glossary.patterns.push(pattern.to_string()),
+                term => glossary.terms.push(term.to_string()),
+            }
+        }
+        return glossary;
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.terms.is_empty() && self.patterns.is_empty();
+    }
+    /// whisper's initial prompt, the terms as a list so it is more likely to spell them that way
+    pub fn prompt(&self) -> String {
+        let mut prompt = String::new();
+        for term in &self.terms {
+            if prompt.len() + term.len() + 2 > MAX_PROMPT_LENGTH {
+                break;
+            }
+            if !prompt.is_empty() {
+                prompt.push_str(", ");
+            }
+            prompt.push_str(term);
+        }
+        if !prompt.is_empty() {
+            prompt.push('.');
+        }
+        return prompt;
+    }
+    /// the single words of the terms, tesseract's user words are matched word by word
+    pub fn words(&self) -> Vec<String> {
+        let mut words: Vec<String> = Vec::new();
+        for word in self.terms.iter().flat_map(|term| term.split_whitespace()) {
+            if !words.iter().any(|w| w == word) {
+                words.push(word.to_string());
+            }
+        }
+        return words;
+    }
+    /// writes `glossary.user-words` and `glossary.user-patterns` to `dir` for tesseract's
+    /// `user_words_file` and `user_patterns_file`, returns their paths
+    pub fn write_tesseract_files(&self, dir: &Path) -> std::io::Result<(PathBuf, PathBuf)> {
+        let words_path = dir.join("glossary.user-words");
+        let patterns_path = dir.join("glossary.user-patterns");
+        fs::write(&words_path, self.words().join("\n") + "\n")?;
+        fs::write(&patterns_path, self.patterns.join("\n") + "\n")?;
+        return Ok((words_path, patterns_path));
+    }
+}
@@ -1,63 +1,907 @@
+use crate::analyzer::Analyzer;
+use crate::eval;
+use crate::ocr::{BoundingBox, OcrResult, OcrWord};
+use crate::query::Query;
+use crate::spell::{self, SymSpell};
 // use crate::trie::Trie;
 use regex::Regex;
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::OnceLock;
 // use stop_words::{get, LANGUAGE};
 
 use serde::{Deserialize, Serialize};
+
+/// number of words kept on each side of a match in a snippet
+const CONTEXT_WORDS: usize = 8;
+/// lines of consecutive frames at least this similar are the same line, OCR is rarely exact
+const SAME_LINE_SIMILARITY: f32 = 0.85;
+/// weight of a down-weighted overlay posting, a normal posting weighs 1
+const OVERLAY_WEIGHT: f32 = 0.1;
+/// an overlay word stays in one cell of a grid this fine, relative to the frame size
+const OVERLAY_CELL: f32 = 0.05;
+/// fewer frames than this are too few to tell an overlay from a slide
+const OVERLAY_MIN_FRAMES: usize = 4;
+/// text this much taller than the frame's median line is a heading
+const TITLE_SIZE: f32 = 1.4;
+/// text centered in this top share of the frame is a heading too
+const TITLE_TOP: f32 = 0.2;
+/// boost of top-of-frame text when ranking, on top of its size
+const TOP_BOOST: f32 = 1.5;
+/// confidence of a word indexed in place of a misread one, see `Indexer::correct_from_speech`
+const CORRECTION_CONFIDENCE: f32 = 0.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Ocr,
+    Asr,
+    Subtitle,
+    /// burned-in captions read from the frames
+    Caption,
+}
+
+impl Source {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Ocr => "ocr",
+            Source::Asr => "asr",
+            Source::Subtitle => "subtitle",
+            Source::Caption => "caption",
+        }
+    }
+}
+
+/// raw text of one frame (OCR) or one segment (ASR), kept to show hits in context
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Passage {
+    pub source: Source,
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+    /// the grammar-corrected text, if it differs, see `Indexer::record_correction`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub corrected: Option<String>,
+}
+
+/// keyword-in-context excerpt of a passage, `highlights` are byte ranges of matched words
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    pub source: Source,
+    pub text: String,
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// splits the snippet into (text, is_match) parts
+    pub fn parts(&self) -> Vec<(&str, bool)> {
+        let mut parts = Vec::new();
+        let mut last = 0;
+        for range in &self.highlights {
+            if range.start > last {
+                parts.push((&self.text[last..range.start], false));
+            }
+            parts.push((&self.text[range.clone()], true));
+            last = range.end;
+        }
+        if last < self.text.len() {
+            parts.push((&self.text[last..], false));
+        }
+        return parts;
+    }
+    /// wraps every matched word in `open` and `close`
+    pub fn highlight(&self, open: &str, close: &str) -> String {
+        return self
+            .parts()
+            .iter()
+            .map(|(part, matched)| match matched {
+                true => format!("{}{}{}", open, part, close),
+                false => part.to_string(),
+            })
+            .collect();
+    }
+}
+
+/// what was on screen and being said at a given time
+pub struct Moment<'a> {
+    /// the frame passage on screen at, or nearest to, the time
+    pub frame: Option<&'a Passage>,
+    /// the spoken (or subtitled) passages covering the time
+    pub segments: Vec<&'a Passage>,
+}
+
+/// part of the frame, all values are relative to the frame size
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Region {
+    pub fn from_bbox(bbox: &BoundingBox, width: u32, height: u32) -> Self {
+        let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+        return Self {
+            x: bbox.left as f32 / width,
+            y: bbox.top as f32 / height,
+            w: bbox.width as f32 / width,
+            h: bbox.height as f32 / height,
+        };
+    }
+    pub fn center(&self) -> (f32, f32) {
+        return (self.x + self.w / 2.0, self.y + self.h / 2.0);
+    }
+    /// true if the center of `other` lies in this region
+    pub fn contains(&self, other: &Region) -> bool {
+        let (x, y) = other.center();
+        return x >= self.x && x <= self.x + self.w && y >= self.y && y <= self.y + self.h;
+    }
+    /// e.g. "top left", from the third of the frame the center falls in
+    pub fn describe(&self) -> String {
+        let (x, y) = self.center();
+        let vertical = ["top", "middle", "bottom"][((y * 3.0) as usize).min(2)];
+        let horizontal = ["left", "center", "right"][((x * 3.0) as usize).min(2)];
+        return format!("{} {}", vertical, horizontal);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub timestamp: u64,
+    /// last time the word was still on screen, OCR only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<u64>,
+    /// where the word was on screen, OCR only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+    /// height of the word's line relative to the median line of the frame, OCR only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<f32>,
+    /// below 1 for static overlays, hits are ranked by their strongest posting
+    #[serde(default = "full_weight", skip_serializing_if = "is_full_weight")]
+    pub weight: f32,
+    /// below 1 for words indexed in place of what OCR read, see `Indexer::correct_from_speech`
+    #[serde(default = "full_weight", skip_serializing_if = "is_full_weight")]
+    pub confidence: f32,
+}
+
+impl Posting {
+    /// large or top-of-frame text, like a slide title
+    pub fn is_title(&self) -> bool {
+        return self.size.is_some_and(|size| size >= TITLE_SIZE) || self.is_top();
+    }
+    fn is_top(&self) -> bool {
+        return self
+            .region
+            .is_some_and(|region| region.center().1 <= TITLE_TOP);
+    }
+    /// the ranking score: headings count more than body text, footnotes less
+    pub fn score(&self) -> f32 {
+        let size = self.size.unwrap_or(1.0).clamp(0.5, 3.0);
+        let boost = match self.is_top() {
+            true => TOP_BOOST,
+            false => 1.0,
+        };
+        return self.weight * self.confidence * size * boost;
+    }
+}
+
+fn full_weight() -> f32 {
+    return 1.0;
+}
+
+fn is_full_weight(weight: &f32) -> bool {
+    return *weight == 1.0;
+}
+
+/// what happens to the postings of text detected as a static overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlayMode {
+    /// keep them, ranked below other hits
+    #[default]
+    Downweight,
+    /// remove them from the index, the text stays in the passages
+    Drop,
+}
+
+impl FromStr for OverlayMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "downweight" => Ok(OverlayMode::Downweight),
+            "drop" => Ok(OverlayMode::Drop),
+            _ => Err(format!(
+                "unknown overlay mode '{}', expected downweight or drop",
+                s
+            )),
+        }
+    }
+}
+
+/// a word that stays on screen for most of the video, like a logo, clock or banner
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Overlay {
+    pub word: String,
+    /// share of the OCRed frames showing the word
+    pub share: f32,
+    /// where the word sits, if it never moves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+}
+
+/// a glossary term and how often the engines produced it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GlossaryTerm {
+    pub term: String,
+    /// frames and captions it was read in
+    pub read: usize,
+    /// transcript segments it was heard in
+    pub heard: usize,
+}
+
+/// a misread OCR word and the word it was snapped to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    /// when the frame showing the word came on
+    pub timestamp: u64,
+    pub read: String,
+    pub corrected: String,
+    /// edits between the two
+    pub distance: usize,
+    /// when the word was said, `None` if it came from the word list
+    pub spoken_at: Option<u64>,
+}
+
+pub struct Hit {
+    pub timestamp: u64,
+    /// last time the matched words were still on screen, OCR only
+    pub end: Option<u64>,
+    /// score of the strongest matched posting, see `Posting::score`
+    pub score: f32,
+    pub snippets: Vec<Snippet>,
+    /// where the matched words were on screen
+    pub regions: Vec<Region>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Metadata {
+    /// path of the indexed video, used to stream it back when serving
+    pub video_path: Option<PathBuf>,
+    /// spoken language, as set or detected by whisper
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// tessdata languages the frames were read with, e.g. `eng+deu`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_lang: Option<String>,
+    /// static overlays found by `Indexer::suppress_overlays`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overlays: Vec<Overlay>,
+    /// the glossary terms, see `Indexer::mark_glossary`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub glossary: Vec<GlossaryTerm>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Indexer {
-    index: HashMap<String, Vec<u64>>,
+    index: HashMap<String, Vec<Posting>>,
+    /// passages keyed by their start time
+    #[serde(default)]
+    passages: BTreeMap<u64, Vec<Passage>>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// saved with the index so queries are split like the indexed text
+    #[serde(default)]
+    pub analyzer: Analyzer,
+    /// lines of the last recorded frame, for slide diffing
+    #[serde(skip)]
+    on_screen: Vec<ShownLine>,
     // trie: Trie,
     // stop_words: Vec<String>,
 }
 
+/// a line of a frame and where its words were indexed
+struct ShownLine {
+    text: String,
+    /// (word, position in the word's postings)
+    postings: Vec<(String, usize)>,
+}
+
 impl Indexer {
     pub fn new() -> Self {
         return Self {
             index: HashMap::new(),
+            passages: BTreeMap::new(),
+            metadata: Metadata::default(),
+            analyzer: Analyzer::default(),
+            on_screen: Vec::new(),
             // trie: Trie::new(),
             // stop_words: get(LANGUAGE::English),
         };
     }
     pub fn update(&mut self, text: &str, timestamp: u64) {
+        self.update_at(text, timestamp, None, None, None);
+    }
+    /// returns the word and position of every new posting
+    fn update_at(
+        &mut self,
+        text: &str,
+        timestamp: u64,
+        end: Option<u64>,
+        region: Option<Region>,
+        size: Option<f32>,
+    ) -> Vec<(String, usize)> {
+        let mut added = Vec::new();
         for word in self.tokenize(text) {
             // self.trie.insert(&word, timestamp);
-            let entry = self.index.entry(word);
-            entry.or_default().push(timestamp);
+            let postings = self.index.entry(word.clone()).or_default();
+            postings.push(Posting {
+                timestamp,
+                end,
+                region,
+                size,
+                weight: 1.0,
+                confidence: 1.0,
+            });
+            added.push((word, postings.len() - 1));
         }
+        return added;
     }
-    pub fn search(&mut self, text: &str) -> Vec<u64> {
-        let mut timestamps = Vec::new();
-        for word in self.tokenize(text) {
-            // if let Some(word_stamps) = self.trie.get_timestamps(&word) {
-            //     if timestamps.is_empty() {
-            //         timestamps = word_stamps;
-            //     } else {
-            //         timestamps.retain(|stamp| word_stamps.contains(stamp));
-            //     }
-            // };
-            if let Some(word_stamps) = self.index.get(&word) {
-                if timestamps.is_empty() {
-                    timestamps = word_stamps.clone();
-                } else {
-                    timestamps.retain(|stamp| word_stamps.contains(stamp));
+    /// indexes `text` at `start` and keeps it as a passage for snippets
+    pub fn record(&mut self, source: Source, start: u64, end: u64, text: &str) {
+        self.update(text, start);
+        self.add_passage(source, start, end, text);
+    }
+    /// indexes every OCR word with its position in the frame
+    pub fn record_ocr(&mut self, start: u64, end: u64, result: &OcrResult) {
+        let lines = result.lines();
+        for (line, size) in lines.iter().zip(line_sizes(&lines)) {
+            for word in line.iter() {
+                let region = Region::from_bbox(&word.bbox, result.width, result.height);
+                self.update_at(&word.text, start, Some(end), Some(region), Some(size));
+            }
+        }
+        self.add_passage(Source::Ocr, start, end, &result.text);
+    }
+    /// like `record_ocr`, but diffs the lines against the previously recorded frame:
+    /// new lines are indexed at `start`, lines still on screen only get their end moved to `end`.
+    /// frames have to be recorded in time order
+    pub fn record_ocr_diff(&mut self, start: u64, end: u64, result: &OcrResult) {
+        let mut previous: Vec<Option<ShownLine>> = std::mem::take(&mut self.on_screen)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut shown = Vec::new();
+        let lines = result.lines();
+        for (line, size) in lines.iter().zip(line_sizes(&lines)) {
+            let text = line
+                .iter()
+                .map(|word| word.text.to_lowercase())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let same = previous.iter_mut().find(|shown| {
+                shown.as_ref().is_some_and(|shown| {
+                    eval::text_similarity(&shown.text, &text) >= SAME_LINE_SIMILARITY
+                })
+            });
+            if let Some(line) = same.and_then(Option::take) {
+                for (word, i) in &line.postings {
+                    if let Some(posting) = self.index.get_mut(word).and_then(|p| p.get_mut(*i)) {
+                        posting.end = Some(end);
+                    }
                 }
+                shown.push(line);
+                continue;
+            }
+            let mut postings = Vec::new();
+            for word in line.iter() {
+                let region = Region::from_bbox(&word.bbox, result.width, result.height);
+                let added = self.update_at(&word.text, start, Some(end), Some(region), Some(size));
+                postings.extend(added);
             }
+            shown.push(ShownLine { text, postings });
         }
-        return timestamps;
+        self.on_screen = shown;
+        self.add_passage(Source::Ocr, start, end, &result.text);
     }
-    pub fn tokenize(&mut self, text: &str) -> HashSet<String> {
-        let re = Regex::new(r"\w+").unwrap();
-        let words: HashSet<String> = re
-            .find_iter(&text.trim().to_lowercase())
-            .map(|m| m.as_str().to_string())
-            // .filter(|w| self.stop_words.contains(w) == false)
+    /// snaps misread OCR words to a word said within `window` seconds of their frame, or to
+    /// any word of `word_list`, e.g. `kubernetcs` on a slide while `kubernetes` is said.
+    /// the dictionary is a `SymSpell` of both, the corrected word is indexed next to the
+    /// read one with `CORRECTION_CONFIDENCE`. run once the transcript is recorded
+    pub fn correct_from_speech(&mut self, word_list: &[String], window: u64) -> Vec<Correction> {
+        // when each word of the transcript was said
+        let mut spoken: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+        let segments = self.passages.values().flatten();
+        for passage in segments.filter(|passage| passage.source == Source::Asr) {
+            for word in self.tokenize(&passage.text) {
+                spoken
+                    .entry(word)
+                    .or_default()
+                    .push((passage.start, passage.end));
+            }
+        }
+        let listed: HashSet<String> = word_list.iter().flat_map(|w| self.tokenize(w)).collect();
+        let mut dictionary = SymSpell::new(2);
+        for word in spoken.keys().chain(&listed) {
+            dictionary.add(word);
+        }
+        if dictionary.is_empty() {
+            return Vec::new();
+        }
+        let mut terms: Vec<&String> = self.index.keys().collect();
+        terms.sort();
+        let mut corrections = Vec::new();
+        let mut added = Vec::new();
+        for term in terms {
+            let max_distance = spell::max_distance(term);
+            // numbers and known words are left as they are
+            if max_distance == 0
+                || dictionary.contains(term)
+                || !term.chars().all(char::is_alphabetic)
+            {
+                continue;
+            }
+            let candidates = dictionary.lookup(term, max_distance);
+            // only words read on screen, they are the ones with an end
+            for posting in self.index[term].iter().filter(|p| p.end.is_some()) {
+                let from = posting.timestamp.saturating_sub(window);
+                let to = posting.end.unwrap_or(posting.timestamp) + window;
+                let snapped = candidates.iter().find_map(|&(word, distance)| {
+                    let said = spoken.get(word).and_then(|times| {
+                        times
+                            .iter()
+                            .find(|(start, end)| *start <= to && *end >= from)
+                    });
+                    match (said, listed.contains(word)) {
+                        (Some((start, _)), _) => Some((word, distance, Some(*start))),
+                        (None, true) => Some((word, distance, None)),
+                        (None, false) => None,
+                    }
+                });
+                let Some((word, distance, spoken_at)) = snapped else {
+                    continue;
+                };
+                corrections.push(Correction {
+                    timestamp: posting.timestamp,
+                    read: term.clone(),
+                    corrected: word.to_string(),
+                    distance,
+                    spoken_at,
+                });
+                added.push((
+                    word.to_string(),
+                    Posting {
+                        confidence: CORRECTION_CONFIDENCE,
+                        ..posting.clone()
+                    },
+                ));
+            }
+        }
+        for (word, posting) in added {
+            self.index.entry(word).or_default().push(posting);
+        }
+        corrections.sort_by_key(|correction| correction.timestamp);
+        return corrections;
+    }
+    /// counts the frames and segments the engines produced each glossary term in, as they read
+    /// or heard it, not corrected. a term of several words counts where all of them are
+    pub fn mark_glossary(&mut self, terms: &[String]) {
+        let passages: Vec<(Source, HashSet<String>)> = self
+            .passages
+            .values()
+            .flatten()
+            .map(|passage| (passage.source, self.tokenize(&passage.text)))
+            .collect();
+        self.metadata.glossary = terms
+            .iter()
+            .filter_map(|term| {
+                let words = self.tokenize(term);
+                if words.is_empty() {
+                    return None;
+                }
+                let (mut read, mut heard) = (0, 0);
+                for (source, _) in passages.iter().filter(|(_, text)| words.is_subset(text)) {
+                    match source {
+                        Source::Ocr | Source::Caption => read += 1,
+                        Source::Asr => heard += 1,
+                        Source::Subtitle => (),
+                    }
+                }
+                return Some(GlossaryTerm {
+                    term: term.clone(),
+                    read,
+                    heard,
+                });
+            })
+            .collect();
+    }
+    /// the glossary terms a query asks for
+    pub fn glossary_terms(&self, query: &Query) -> Vec<&GlossaryTerm> {
+        let words = self.tokenize(&query.all_text());
+        return self
+            .metadata
+            .glossary
+            .iter()
+            .filter(|glossary| {
+                let terms = self.tokenize(&glossary.term);
+                !terms.is_empty() && terms.is_subset(&words)
+            })
+            .collect();
+    }
+    /// finds words shown in more than `share` of the OCRed frames, or in `fixed_share`
+    /// of them at the same spot, and down-weights or drops their postings.
+    /// run once every frame is recorded, a share of 0 turns its test off
+    pub fn suppress_overlays(
+        &mut self,
+        share: f32,
+        fixed_share: f32,
+        mode: OverlayMode,
+    ) -> &[Overlay] {
+        let mut frames: Vec<u64> = self
+            .passages
+            .values()
+            .flatten()
+            .filter(|passage| passage.source == Source::Ocr)
+            .map(|passage| passage.start)
+            .collect();
+        frames.dedup();
+        if (share <= 0.0 && fixed_share <= 0.0) || frames.len() < OVERLAY_MIN_FRAMES {
+            return &self.metadata.overlays;
+        }
+        self.on_screen.clear();
+        let share_of = |shown: &HashSet<usize>| shown.len() as f32 / frames.len() as f32;
+        let mut overlays = Vec::new();
+        for (word, postings) in self.index.iter_mut() {
+            // the frames showing the word, anywhere and in each cell of the screen
+            let mut shown = HashSet::new();
+            let mut cells: HashMap<(i32, i32), (Region, HashSet<usize>)> = HashMap::new();
+            for posting in postings.iter() {
+                let Some(region) = posting.region else {
+                    continue;
+                };
+                // a frame shows the word if a posting is on screen at its start,
+                // with slide diffing a line is only indexed in the first frame showing it
+                let end = posting.end.unwrap_or(posting.timestamp);
+                let first = frames.partition_point(|frame| *frame < posting.timestamp);
+                let last = frames.partition_point(|frame| *frame <= end);
+                let (_, in_cell) = cells
+                    .entry(cell(&region))
+                    .or_insert((region, HashSet::new()));
+                shown.extend(first..last);
+                in_cell.extend(first..last);
+            }
+            let fixed = cells
+                .into_iter()
+                .map(|(key, (region, shown))| (key, region, share_of(&shown)))
+                .max_by(|a, b| a.2.total_cmp(&b.2).then(b.0.cmp(&a.0)));
+            let overlay = match fixed {
+                Some((_, region, fixed)) if fixed_share > 0.0 && fixed >= fixed_share => Overlay {
+                    word: word.clone(),
+                    share: fixed,
+                    region: Some(region),
+                },
+                _ if share > 0.0 && share_of(&shown) >= share => Overlay {
+                    word: word.clone(),
+                    share: share_of(&shown),
+                    region: None,
+                },
+                _ => continue,
+            };
+            let is_overlay = |posting: &Posting| match (&overlay.region, &posting.region) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(fixed), Some(region)) => cell(fixed) == cell(region),
+            };
+            match mode {
+                OverlayMode::Downweight => postings
+                    .iter_mut()
+                    .filter(|p| is_overlay(p))
+                    .for_each(|p| p.weight = OVERLAY_WEIGHT),
+                OverlayMode::Drop => postings.retain(|p| !is_overlay(p)),
+            }
+            overlays.push(overlay);
+        }
+        self.index.retain(|_, postings| !postings.is_empty());
+        overlays.sort_by(|a, b| b.share.total_cmp(&a.share).then(a.word.cmp(&b.word)));
+        self.metadata.overlays = overlays;
+        return &self.metadata.overlays;
+
+        /// the cell of the screen grid the center of `region` falls in
+        fn cell(region: &Region) -> (i32, i32) {
+            let (x, y) = region.center();
+            return ((x / OVERLAY_CELL) as i32, (y / OVERLAY_CELL) as i32);
+        }
+    }
+    fn add_passage(&mut self, source: Source, start: u64, end: u64, text: &str) {
+        self.passages.entry(start).or_default().push(Passage {
+            source,
+            start,
+            end,
+            text: text.to_string(),
+            corrected: None,
+        });
+    }
+    /// the text of every passage of `source`, with its start and position among the
+    /// passages starting then
+    pub fn passage_texts(&self, source: Source) -> Vec<(u64, usize, String)> {
+        return self
+            .passages
+            .iter()
+            .flat_map(|(start, passages)| {
+                passages
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, passage)| passage.source == source)
+                    .map(|(i, passage)| (*start, i, passage.text.clone()))
+            })
+            .collect();
+    }
+    /// indexes the words of the corrected form of a passage that its raw text doesn't have,
+    /// so either form is found, and keeps the corrected text for snippets
+    pub fn record_correction(&mut self, start: u64, i: usize, corrected: &str) {
+        let Some(passage) = self.passages.get(&start).and_then(|p| p.get(i)) else {
+            return;
+        };
+        if corrected.trim().is_empty() || corrected.trim() == passage.text.trim() {
+            return;
+        }
+        // only OCR postings know when their text left the screen
+        let end = (passage.source == Source::Ocr).then_some(passage.end);
+        let raw = self.tokenize(&passage.text);
+        for word in self.tokenize(corrected).difference(&raw) {
+            self.index.entry(word.clone()).or_default().push(Posting {
+                timestamp: start,
+                end,
+                region: None,
+                size: None,
+                weight: 1.0,
+                confidence: 1.0,
+            });
+        }
+        if let Some(passage) = self.passages.get_mut(&start).and_then(|p| p.get_mut(i)) {
+            passage.corrected = Some(corrected.to_string());
+        }
+    }
+    /// runs a query, with the matching context of every timestamp.
+    /// hits are ranked by score, equal scores stay in time order
+    pub fn search_hits(&self, query: &Query) -> Vec<Hit> {
+        let terms = self.tokenize(&query.all_text());
+        let mut hits: Vec<Hit> = self
+            .find(query)
+            .into_iter()
+            .map(|(timestamp, postings)| Hit {
+                timestamp,
+                end: postings.iter().filter_map(|p| p.end).max(),
+                score: postings.iter().map(|p| p.score()).fold(0.0, f32::max),
+                snippets: self.snippets(timestamp, &terms),
+                regions: postings.iter().filter_map(|p| p.region).collect(),
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        return hits;
+    }
+    /// one snippet per passage starting at `timestamp` that contains any of `terms`
+    pub fn snippets(&self, timestamp: u64, terms: &HashSet<String>) -> Vec<Snippet> {
+        let Some(passages) = self.passages.get(&timestamp) else {
+            return Vec::new();
+        };
+        return passages
+            .iter()
+            .filter_map(|passage| {
+                // words only the corrected form has are shown in it
+                self.snippet(passage.source, &passage.text, terms)
+                    .or_else(|| {
+                        let corrected = passage.corrected.as_deref()?;
+                        self.snippet(passage.source, corrected, terms)
+                    })
+            })
             .collect();
+    }
+    fn snippet(&self, source: Source, text: &str, terms: &HashSet<String>) -> Option<Snippet> {
+        let words: Vec<Range<usize>> = spans().find_iter(text).map(|m| m.range()).collect();
+        // split by the index's analyzer, so `foo_bar` or `a.b` match in code mode
+        let is_match = |word: &Range<usize>| {
+            self.tokenize(&text[word.clone()])
+                .iter()
+                .any(|term| terms.contains(term))
+        };
+        let first = words.iter().position(is_match)?;
+
+        let from = first.saturating_sub(CONTEXT_WORDS);
+        let to = (first + CONTEXT_WORDS).min(words.len() - 1);
+        let (start, end) = (words[from].start, words[to].end);
+        let prefix = if from > 0 { "… " } else { "" };
+        let suffix = if to < words.len() - 1 { " …" } else { "" };
+        // whitespace is flattened one byte for one byte so the ranges stay valid
+        let body: String = text[start..end]
+            .chars()
+            .map(|c| {
+                if c.is_whitespace() && c.len_utf8() == 1 {
+                    ' '
+                } else {
+                    c
+                }
+            })
+            .collect();
+        let highlights = words[from..=to]
+            .iter()
+            .filter(|word| is_match(word))
+            .map(|word| {
+                let words = self.tokenize(&text[word.clone()]);
+                let word = match_range(text, word.clone(), &words, terms);
+                word.start - start + prefix.len()..word.end - start + prefix.len()
+            })
+            .collect();
+        return Some(Snippet {
+            source,
+            text: format!("{}{}{}", prefix, body, suffix),
+            highlights,
+        });
+    }
+    /// the sampled frames around text shown from `start` to `end`: when the frame before it
+    /// left the screen, and when the first frame after it came on, `None` at either end of the video
+    pub fn frames_around(&self, start: u64, end: u64) -> (Option<u64>, Option<u64>) {
+        let is_frame = |passage: &&Passage| passage.source == Source::Ocr;
+        let before = self
+            .passages
+            .range(..start)
+            .rev()
+            .find_map(|(_, passages)| passages.iter().find(is_frame))
+            .map(|passage| passage.end);
+        let after = self
+            .passages
+            .range(end + 1..)
+            .find_map(|(_, passages)| passages.iter().find(is_frame))
+            .map(|passage| passage.start);
+        return (before, after);
+    }
+    /// reverse lookup, returns the OCR text and ASR segments around `timestamp`
+    pub fn at(&self, timestamp: u64) -> Moment<'_> {
+        let is_frame = |passage: &&Passage| passage.source == Source::Ocr;
+        let before = self
+            .passages
+            .range(..=timestamp)
+            .rev()
+            .find_map(|(_, passages)| passages.iter().find(is_frame));
+        let after = self
+            .passages
+            .range(timestamp..)
+            .find_map(|(_, passages)| passages.iter().find(is_frame));
+        let frame = match (before, after) {
+            // the frame is still on screen
+            (Some(before), _) if before.end >= timestamp => Some(before),
+            (Some(before), Some(after)) => {
+                match timestamp - before.end <= after.start - timestamp {
+                    true => Some(before),
+                    false => Some(after),
+                }
+            }
+            (before, after) => before.or(after),
+        };
+        let segments = self
+            .passages
+            .range(..=timestamp)
+            .flat_map(|(_, passages)| passages.iter())
+            .filter(|passage| passage.source != Source::Ocr && passage.end >= timestamp)
+            .collect();
+        return Moment { frame, segments };
+    }
+    pub fn search(&self, text: &str) -> Vec<u64> {
+        let query = Query {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        return self.find(&query).into_keys().collect();
+    }
+    /// the postings of every timestamp where all indexed query words appear
+    fn find(&self, query: &Query) -> BTreeMap<u64, Vec<&Posting>> {
+        let mut found: Option<BTreeMap<u64, Vec<&Posting>>> = None;
+        let words = self.tokenize(&query.text).into_iter().map(|w| (w, false));
+        let titles = self.tokenize(&query.title).into_iter().map(|w| (w, true));
+        for (word, title) in words.chain(titles) {
+            let Some(postings) = self.index.get(&word) else {
+                continue;
+            };
+            let mut by_time: BTreeMap<u64, Vec<&Posting>> = BTreeMap::new();
+            let accepted = postings
+                .iter()
+                .filter(|p| accepts(query, p) && (!title || p.is_title()));
+            for posting in accepted {
+                by_time.entry(posting.timestamp).or_default().push(posting);
+            }
+            found = match found {
+                None => Some(by_time),
+                Some(mut found) => {
+                    found.retain(|timestamp, _| by_time.contains_key(timestamp));
+                    for (timestamp, postings) in by_time {
+                        if let Some(matched) = found.get_mut(&timestamp) {
+                            matched.extend(postings);
+                        }
+                    }
+                    Some(found)
+                }
+            };
+        }
+        return found.unwrap_or_default();
+
+        fn accepts(query: &Query, posting: &Posting) -> bool {
+            match (&query.region, &posting.region) {
+                (None, _) => true,
+                (Some(filter), Some(region)) => filter.contains(region),
+                (Some(_), None) => false,
+            }
+        }
+    }
+    /// the words of `text` that are in the index, the ones a query matches on
+    pub fn indexed_terms(&self, text: &str) -> HashSet<String> {
+        let mut terms = self.tokenize(text);
+        terms.retain(|term| self.index.contains_key(term));
+        return terms;
+    }
+    pub fn tokenize(&self, text: &str) -> HashSet<String> {
+        let words = self.analyzer.tokenize(text);
+        // .filter(|w| self.stop_words.contains(w) == false)
         return words;
     }
 
     pub fn serialize(&self) -> Value {
-        return serde_json::to_value(&self.index).unwrap();
+        return serde_json::to_value(self).unwrap();
     }
 }
+
+// compiled on first use, every hit builds its snippets
+static SPANS: OnceLock<Regex> = OnceLock::new();
+
+/// the runs of text between whitespace, the words of a snippet
+fn spans() -> &'static Regex {
+    return SPANS.get_or_init(|| Regex::new(r"\S+").unwrap());
+}
+
+/// the part of `range` from the first to the last of its `words` that are in `terms`,
+/// so `[hashmap::new]()` is highlighted without the punctuation around it
+fn match_range(
+    text: &str,
+    range: Range<usize>,
+    words: &HashSet<String>,
+    terms: &HashSet<String>,
+) -> Range<usize> {
+    let word = text[range.clone()].to_lowercase();
+    // lowercasing changed the byte offsets, keep the whole word
+    if word.len() != range.len() {
+        return range;
+    }
+    let (mut start, mut end) = (word.len(), 0);
+    for term in words.intersection(terms) {
+        for (at, _) in word.match_indices(term.as_str()) {
+            start = start.min(at);
+            end = end.max(at + term.len());
+        }
+    }
+    if start >= end {
+        return range;
+    }
+    return range.start + start..range.start + end;
+}
+
+/// the height of every line relative to the median line height, 1.0 for body text
+fn line_sizes(lines: &[&[OcrWord]]) -> Vec<f32> {
+    let heights: Vec<u32> = lines
+        .iter()
+        .map(|line| line.iter().map(|word| word.bbox.height).max().unwrap_or(0))
+        .collect();
+    let mut sorted = heights.clone();
+    sorted.sort();
+    // the lower median, so the body line of a slide with a title and one line is the measure
+    let median = sorted
+        .get(sorted.len().saturating_sub(1) / 2)
+        .copied()
+        .unwrap_or(1)
+        .max(1);
+    return heights
+        .iter()
+        .map(|height| *height as f32 / median as f32)
+        .collect();
+}
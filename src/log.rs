@@ -2,17 +2,28 @@ use crate::ExitCode;
 pub fn log(error: ExitCode) {
     match error {
         ExitCode::Success => (),
-        ExitCode::InvalidArgs => eprintln!("Usage: vcf [video_path]"),
+        ExitCode::InvalidArgs => {
+            eprintln!("Usage: vcf [video_path] [--option value]...");
+            eprintln!("       vcf serve [dump_dir]... [--port port]");
+            eprintln!("       vcf eval-ocr [frames_dir] [--ocr-ensemble members]");
+            eprintln!("       vcf info [video_path] [HH:MM:SS] [--roi areas] [--exclude areas]");
+        }
+        ExitCode::InvalidOption(info) => eprintln!("Invalid option, reason: \n{}", info),
         ExitCode::InvalidPath => {
             eprintln!("Invalid File Path, Make sure the path you provided is correct")
         }
         ExitCode::KeyframesError(info) => {
-            eprintln!("Failed to extract keyframes, reason: \n{}", info)
+            eprintln!("Failed to extract frames, reason: \n{}", info)
         }
         ExitCode::WavConversionError(info) => {
             eprintln!("Failed to convert to WAV, reason: \n{}", info)
         }
         ExitCode::FFProbeError(info) => eprintln!("FFprobe error, reason: \n{}", info),
         ExitCode::SaveError(info) => eprintln!("Failed to save index, reason: \n{}", info),
+        ExitCode::LoadError(info) => eprintln!("Failed to load index, reason: \n{}", info),
+        ExitCode::ServeError(info) => eprintln!("Failed to start server, reason: \n{}", info),
+        ExitCode::OcrError(info) => eprintln!("Failed to initialize OCR, reason: \n{}", info),
+        ExitCode::PreviewError(info) => eprintln!("Failed to render preview, reason: \n{}", info),
+        ExitCode::AsrError(info) => eprintln!("Failed to transcribe audio, reason: \n{}", info),
     }
 }
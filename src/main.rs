@@ -1,30 +1,62 @@
 #![allow(clippy::needless_return)]
 
+mod analyzer;
 mod asr;
+mod captions;
 mod config;
+mod dedup;
 mod disk;
+mod draw;
+mod ensemble;
+mod eval;
 mod gec;
+mod glossary;
 mod indexer;
 mod log;
+mod mask;
 mod ocr;
+mod preprocessing;
+mod query;
+mod refine;
+mod serve;
+mod spell;
+mod subtitles;
+mod tessapi;
 mod tests;
+mod textdetect;
 mod trie;
 mod vidsplicer;
 
-use indexer::Indexer;
+use asr::Segment;
+use config::Options;
+use dedup::Shot;
+use glossary::Glossary;
+use image::DynamicImage;
+use indexer::{Indexer, Source};
 use log::log;
+use mask::Mask;
+use ocr::{BoundingBox, OcrEngine, OcrResult};
+use preprocessing::Pipeline;
+use query::Query;
+use refine::Refiner;
+use subtitles::Cue;
 
 use std::{
+    collections::HashMap,
     io::{self, Write},
     path::{Path, PathBuf},
     process::exit,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 use threadpool::ThreadPool;
 use vidsplicer::{
     ffmpeg_utils,
-    ffmpeg_utils::{FFmpegResult, FFprobeResult},
-    frames_iterator::VideoFramesIterator,
+    ffmpeg_utils::{FFmpegResult, FFprobeResult, Sampling},
+    frames_iterator::{PixelFormat, VideoFramesIterator},
 };
 
 enum PipelineResult {
@@ -35,26 +67,57 @@ enum PipelineResult {
 pub enum ExitCode {
     Success,
     InvalidArgs,
+    InvalidOption(String),
     InvalidPath,
     SaveError(String),
     KeyframesError(String),
     WavConversionError(String),
     FFProbeError(String),
+    LoadError(String),
+    ServeError(String),
+    OcrError(String),
+    PreviewError(String),
+    AsrError(String),
 }
 fn main() {
-    // iterate_frames("data/patterns.mp4");
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        log(ExitCode::InvalidArgs);
-        exit(1);
-    }
-    let path = PathBuf::from(&args[1]);
-    match demo(&path) {
-        PipelineResult::Error(code) => {
-            eprintln!("Pipeline error, check the logs");
-            log(code);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (options, args) = match Options::parse(&args) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            log(ExitCode::InvalidOption(error));
+            exit(1);
+        }
+    };
+    match args.first().map(String::as_str) {
+        None => {
+            log(ExitCode::InvalidArgs);
+            exit(1);
+        }
+        Some("serve") => {
+            if let Err(code) = serve::serve(&args[1..], &options) {
+                log(code);
+                exit(1);
+            }
+        }
+        Some("info") => {
+            if let Err(code) = info(&args[1..], &options) {
+                log(code);
+                exit(1);
+            }
+        }
+        Some("eval-ocr") => {
+            if let Err(code) = eval_ocr(&args[1..], &options) {
+                log(code);
+                exit(1);
+            }
         }
-        PipelineResult::Success(indexer) => init_ui(indexer),
+        Some(path) => match demo(&PathBuf::from(path), &options) {
+            PipelineResult::Error(code) => {
+                eprintln!("Pipeline error, check the logs");
+                log(code);
+            }
+            PipelineResult::Success(indexer) => init_ui(indexer, &options),
+        },
     }
 }
 fn format_timestamp(seconds: u64) -> String {
@@ -64,32 +127,218 @@ fn format_timestamp(seconds: u64) -> String {
     format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
 }
 
-fn init_ui(mut index: Indexer) {
+/// `HH:MM:SS.mmm`
+fn format_precise(seconds: f64) -> String {
+    let ms = (seconds * 1000.0).round() as u64;
+    format!("{}.{:03}", format_timestamp(ms / 1000), ms % 1000)
+}
+
+/// parses `HH:MM:SS`, `MM:SS` or plain seconds
+fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    let mut seconds = 0;
+    let parts: Vec<&str> = timestamp.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for part in parts {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+    return Some(seconds);
+}
+
+fn print_moment(index: &Indexer, timestamp: u64) {
+    let moment = index.at(timestamp);
+    match moment.frame {
+        Some(frame) => println!(
+            "[ocr] {}\n{}",
+            format_timestamp(frame.start),
+            frame.text.trim()
+        ),
+        None => println!("[ocr] no frames"),
+    }
+    if moment.segments.is_empty() {
+        println!("[asr] nothing said");
+    }
+    for segment in moment.segments {
+        println!(
+            "[asr] {} - {}\n{}",
+            format_timestamp(segment.start),
+            format_timestamp(segment.end),
+            segment.text.trim()
+        );
+    }
+    println!();
+}
+
+fn init_ui(index: Indexer, options: &Options) {
+    // decoding and reading frames one by one is slow, only the first hits are refined
+    const REFINED_HITS: usize = 5;
+    let mut refiner = match (options.refine, &index.metadata.video_path) {
+        (true, Some(video_path)) => match Refiner::new(video_path, options) {
+            Ok(refiner) => Some(refiner),
+            Err(error) => {
+                eprintln!("Hits won't be refined, reason: \n{}", error);
+                None
+            }
+        },
+        _ => None,
+    };
     print!("Enter word to search");
     println!("or enter 'q' to exit");
-    let mut query = String::new();
+    println!("':at HH:MM:SS' shows what was on screen and said at that time");
+    println!(
+        "'region:top' (or bottom, left, x,y,w,h...) only matches text in that part of the frame"
+    );
+    let mut input = String::new();
     loop {
+        input.clear();
         print!("Search >>> ");
         let _ = io::stdout().flush();
-        io::stdin().read_line(&mut query).expect("Failed to query");
-        query = query.trim().to_lowercase();
+        io::stdin().read_line(&mut input).expect("Failed to query");
+        let query = input.trim().to_lowercase();
         if query == "q" {
             break;
         }
-        let timestamps = index.search(&query);
-        if timestamps.is_empty() {
+        if let Some(timestamp) = query.strip_prefix(":at ") {
+            match parse_timestamp(timestamp) {
+                Some(timestamp) => print_moment(&index, timestamp),
+                None => println!("Invalid timestamp, expected HH:MM:SS"),
+            }
+            continue;
+        }
+        let query = match Query::parse(&query) {
+            Ok(query) => query,
+            Err(error) => {
+                println!("{}", error);
+                continue;
+            }
+        };
+        for term in index.glossary_terms(&query) {
+            println!(
+                "'{}' is a glossary term, read in {} frames and heard in {} segments",
+                term.term, term.read, term.heard
+            );
+        }
+        let hits = index.search_hits(&query);
+        if hits.is_empty() {
             println!("Not found");
             continue;
         }
-        print!("[");
-        timestamps
-            .iter()
-            .for_each(|t| print!("{}, ", format_timestamp(*t)));
-        println!("]\n");
-        query.clear();
+        let terms = index.indexed_terms(&query.all_text());
+        for (i, hit) in hits.iter().enumerate() {
+            println!("{}", format_timestamp(hit.timestamp));
+            for snippet in &hit.snippets {
+                println!(
+                    "    [{}] {}",
+                    snippet.source.name(),
+                    snippet.highlight("\x1b[1m", "\x1b[0m")
+                );
+            }
+            if let Some(region) = hit.regions.first() {
+                println!("    on screen: {}", region.describe());
+            }
+            if let Some(refiner) = refiner.as_mut().filter(|_| i < REFINED_HITS) {
+                match refiner.refine(&index, hit, &terms) {
+                    Ok(Some(refined)) => println!(
+                        "    shown from {} to {}",
+                        format_precise(refined.start),
+                        format_precise(refined.end)
+                    ),
+                    Ok(None) => (),
+                    Err(error) => eprintln!("Failed to refine the hit, reason: \n{}", error),
+                }
+            }
+        }
+        println!();
+    }
+}
+/// prints the video's metadata and renders a frame with the areas of interest (green)
+/// and the excluded areas (red) to `dump/<video>/regions.png`
+fn info(args: &[String], options: &Options) -> Result<(), ExitCode> {
+    let Some(video_path) = args.first().map(Path::new) else {
+        return Err(ExitCode::InvalidArgs);
+    };
+    if !video_path.exists() {
+        return Err(ExitCode::InvalidPath);
+    }
+    let metadata = match ffmpeg_utils::get_video_metadata(video_path) {
+        FFprobeResult::Failure(error) => return Err(ExitCode::FFProbeError(error)),
+        FFprobeResult::Success(metadata) => metadata,
+    };
+    println!("{}", video_path.display());
+    println!(
+        "{}x{}, {} fps, {} frames, {}",
+        metadata.width,
+        metadata.height,
+        metadata.fps,
+        metadata.num_frames,
+        format_timestamp(metadata.duration as u64)
+    );
+    // a tenth into the video skips most intros and black first frames
+    let seconds = match args.get(1) {
+        Some(timestamp) => parse_timestamp(timestamp).ok_or(ExitCode::InvalidArgs)? as f64,
+        None => metadata.duration / 10.0,
+    };
+    let dump_path = PathBuf::from("dump").join(video_path.file_stem().unwrap());
+    disk::create_dump(&dump_path);
+    let preview_path = dump_path.join("preview.png");
+    if let FFmpegResult::Failure(error) =
+        ffmpeg_utils::extract_frame(video_path, seconds, &preview_path)
+    {
+        return Err(ExitCode::KeyframesError(error));
+    }
+    let frame = image::open(&preview_path).map_err(|e| ExitCode::PreviewError(e.to_string()))?;
+    let (width, height) = (frame.width(), frame.height());
+    for (name, areas) in [
+        ("roi", &options.mask.include),
+        ("exclude", &options.mask.exclude),
+    ] {
+        for area in areas {
+            let bbox = area.to_bbox(width, height);
+            println!(
+                "{}: {},{},{},{}px",
+                name, bbox.left, bbox.top, bbox.width, bbox.height
+            );
+        }
     }
+    let regions_path = dump_path.join("regions.png");
+    draw::draw_mask(&frame, &options.mask)
+        .save(&regions_path)
+        .map_err(|e| ExitCode::PreviewError(e.to_string()))?;
+    println!(
+        "Saved the frame at {} to {}",
+        format_timestamp(seconds as u64),
+        regions_path.display()
+    );
+    return Ok(());
 }
-fn demo(video_path: &Path) -> PipelineResult {
+/// scores each OCR ensemble member and their vote against a labelled frame set,
+/// `data/ocr_eval` by default
+fn eval_ocr(args: &[String], options: &Options) -> Result<(), ExitCode> {
+    let dir = Path::new(args.first().map_or("data/ocr_eval", String::as_str));
+    if !dir.is_dir() {
+        return Err(ExitCode::InvalidPath);
+    }
+    let members = match options.ocr_ensemble.is_empty() {
+        true => {
+            ensemble::parse_members(ensemble::DEFAULT_MEMBERS).map_err(ExitCode::InvalidOption)?
+        }
+        false => options.ocr_ensemble.clone(),
+    };
+    let scores = ensemble::evaluate(dir, &members, &options.ocr).map_err(ExitCode::OcrError)?;
+    println!("{:<32} {:>7} {:>7} {:>9}", "", "CER", "WER", "ms/frame");
+    for score in scores {
+        println!(
+            "{:<32} {:>6.1}% {:>6.1}% {:>9.0}",
+            score.name,
+            score.cer * 100.0,
+            score.wer * 100.0,
+            score.ms_per_frame
+        );
+    }
+    return Ok(());
+}
+fn demo(video_path: &Path, options: &Options) -> PipelineResult {
     if !video_path.exists() {
         return PipelineResult::Error(ExitCode::InvalidPath);
     }
@@ -101,13 +350,45 @@ fn demo(video_path: &Path) -> PipelineResult {
 
     disk::create_dump(&dump_path);
 
+    //--------------Glossary--------------//
+    let glossary = match &options.glossary {
+        Some(path) => match Glossary::load(path) {
+            Ok(glossary) => glossary,
+            Err(error) => return PipelineResult::Error(ExitCode::InvalidOption(error)),
+        },
+        None => Glossary::default(),
+    };
+    let mut options = options.clone();
+    if !glossary.is_empty() {
+        let (words, patterns) = match glossary.write_tesseract_files(&dump_path) {
+            Ok(paths) => paths,
+            Err(error) => return PipelineResult::Error(ExitCode::SaveError(error.to_string())),
+        };
+        options.ocr.user_words = Some(words.to_string_lossy().into_owned());
+        options.ocr.user_patterns = Some(patterns.to_string_lossy().into_owned());
+        options.asr.prompt = glossary.prompt();
+        println!(
+            "Biasing OCR and ASR towards {} glossary terms...",
+            glossary.terms.len() + glossary.patterns.len()
+        );
+    }
+    let options = &options;
+
     //--------------Video Splicing--------------//
-    match ffmpeg_utils::extract_keyframes(video_path, &frames_path) {
-        FFmpegResult::Failure(error) => {
-            return PipelineResult::Error(ExitCode::KeyframesError(error));
+    let fps = match ffmpeg_utils::get_video_metadata(video_path) {
+        FFprobeResult::Failure(error) => {
+            return PipelineResult::Error(ExitCode::FFProbeError(error))
         }
-        FFmpegResult::Success(_) => {
-            println!("Successfully extracted keyframes...");
+        FFprobeResult::Success(metadata) => metadata.fps,
+    };
+    if !options.frames_in_memory {
+        match ffmpeg_utils::extract_frames(video_path, &frames_path, options.sampling, fps) {
+            FFmpegResult::Failure(error) => {
+                return PipelineResult::Error(ExitCode::KeyframesError(error));
+            }
+            FFmpegResult::Success(_) => {
+                println!("Successfully extracted frames ({:?})...", options.sampling);
+            }
         }
     }
     match ffmpeg_utils::conv2wav(video_path, &dump_path) {
@@ -120,32 +401,83 @@ fn demo(video_path: &Path) -> PipelineResult {
     }
 
     //------------------------------------------//
-    let indexer = Indexer::new();
+    let mut indexer = Indexer::new();
+    indexer.metadata.video_path =
+        Some(std::fs::canonicalize(video_path).unwrap_or(video_path.to_path_buf()));
+    indexer.metadata.ocr_lang = Some(options.ocr.lang.clone());
+    indexer.analyzer = options.analyzer;
 
     //--------------OCR--------------//
-    let fps = match ffmpeg_utils::get_video_metadata(video_path) {
-        FFprobeResult::Failure(error) => {
-            return PipelineResult::Error(ExitCode::FFProbeError(error))
-        }
-        FFprobeResult::Success(metadata) => metadata.fps,
+    // the captions are read on their own, keep them out of the frame text
+    let mut frame_options = options.clone();
+    if options.captions {
+        frame_options.mask.exclude.push(options.caption_band);
+    }
+    let ocr_result = match options.frames_in_memory {
+        true => ocr_stream(video_path, &dump_path, indexer, &frame_options),
+        false => ocr_dir(&frames_path, fps, indexer, &frame_options),
     };
-
-    let indexer = match ocr_dir(&frames_path, fps, indexer) {
+    let indexer = match ocr_result {
         PipelineResult::Error(code) => return PipelineResult::Error(code),
         PipelineResult::Success(indexer) => {
             println!("Successfully applied OCR...");
             indexer
         }
     };
+    //--------------Burned-in captions--------------//
+    let mut indexer = indexer;
+    if options.captions {
+        match ocr_captions(video_path, &dump_path, &mut indexer, options) {
+            Ok(num_captions) => {
+                println!("Successfully read {} burned-in captions...", num_captions)
+            }
+            Err(code) => return PipelineResult::Error(code),
+        }
+    }
+    //--------------Subtitles--------------//
+    // only subtitle tracks and sidecars skip ASR, not captions read from the frames
+    let num_cues = ingest_subtitles(video_path, &dump_path, &mut indexer);
+    if num_cues > 0 {
+        println!("Successfully indexed {} subtitle cues...", num_cues);
+    }
     //--------------ASR--------------//
-    let indexer = match asr_audio(&dump_path.join("audio.wav"), indexer) {
-        PipelineResult::Error(code) => return PipelineResult::Error(code),
-        PipelineResult::Success(indexer) => {
-            println!("Successfully applied ASR...");
-            indexer
+    let indexer = if num_cues > 0 && options.skip_asr_with_subtitles {
+        println!("Skipping ASR, the video has subtitles...");
+        indexer
+    } else {
+        match asr_audio(&dump_path, indexer, options) {
+            PipelineResult::Error(code) => return PipelineResult::Error(code),
+            PipelineResult::Success(indexer) => {
+                println!("Successfully applied ASR...");
+                indexer
+            }
         }
     };
 
+    //--------------Cross-modal spelling correction--------------//
+    let mut indexer = indexer;
+    if options.spell_correct {
+        let mut word_list = match &options.word_list {
+            Some(path) => match disk::load_word_list(path) {
+                Ok(words) => words,
+                Err(error) => {
+                    eprintln!("Failed to read {}, reason: \n{}", path.display(), error);
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        word_list.extend(glossary.terms.iter().cloned());
+        let corrections = indexer.correct_from_speech(&word_list, options.spell_window);
+        if let Err(error) = disk::save_corrections(&corrections, &dump_path.join("corrections.tsv"))
+        {
+            return PipelineResult::Error(ExitCode::SaveError(error.to_string()));
+        }
+        println!("Corrected {} misread words...", corrections.len());
+    }
+
+    indexer.mark_glossary(&glossary.terms);
+
     //--------------Serialize and Save --------------//
     match disk::save_as_json(indexer.serialize(), &dump_path.join("index.json")) {
         Ok(_) => {
@@ -158,78 +490,458 @@ fn demo(video_path: &Path) -> PipelineResult {
         }
     }
 }
-fn asr_audio(audio_path: &Path, mut indexer: Indexer) -> PipelineResult {
-    let ctx = asr::asr(audio_path);
-    for i in 0..ctx.full_n_segments() {
-        let segment = ctx.full_get_segment_text(i).expect("failed to get segment");
-        let start_timestamp = ctx.full_get_segment_t0(i);
-        let _ = ctx.full_get_segment_t1(i);
-        indexer.update(&segment, start_timestamp as u64);
+/// reads the caption band of densely sampled frames into `captions.srt` and `captions.vtt`
+/// and indexes the cues as `caption`, returns the number of cues
+fn ocr_captions(
+    video_path: &Path,
+    dump_path: &Path,
+    indexer: &mut Indexer,
+    options: &Options,
+) -> Result<usize, ExitCode> {
+    let (width, height) = ffmpeg_utils::get_video_dims(video_path)
+        .map_err(|error| ExitCode::FFProbeError(format!("{:?}", error)))?;
+    let band = options.caption_band.to_bbox(width as u32, height as u32);
+    let crop = Some((band.left, band.top, band.width, band.height));
+    let frames = VideoFramesIterator::cropped(
+        video_path,
+        options.caption_sampling,
+        PixelFormat::Gray,
+        crop,
+    )
+    .map_err(|error| ExitCode::KeyframesError(error.to_string()))?;
+    // a band holds one or two lines of a single block of text
+    let mut caption_options = options.clone();
+    caption_options.ocr.psm = 6;
+    caption_options.mask = Mask::default();
+    caption_options.debug_boxes = false;
+    // frames without a caption end the one before, so every frame time is kept
+    let mut times = Vec::new();
+    let shots = frames.map(|frame| {
+        let time = (frame.pts * 1000.0) as u64;
+        times.push(time);
+        Shot {
+            frame: OcrInput::Image(frame.into_image()),
+            start: time,
+            end: time,
+        }
+    });
+    let results = recognize_shots(shots, &dump_path.join("debug"), &caption_options)?;
+    let mut read: HashMap<u64, String> = results
+        .iter()
+        .map(|(time, _, result)| (*time, captions::caption_text(result)))
+        .collect();
+    let frames: Vec<(u64, String)> = times
+        .into_iter()
+        .map(|time| (time, read.remove(&time).unwrap_or_default()))
+        .collect();
+    let frame_gap = match options.caption_sampling {
+        Sampling::Interval(seconds) => (seconds * 1000.0) as u64,
+        _ => 1000,
+    };
+    let cues = captions::stabilize(&frames, frame_gap);
+    subtitles::save(&cues, dump_path, "captions")
+        .map_err(|error| ExitCode::SaveError(error.to_string()))?;
+    for cue in &cues {
+        indexer.record(Source::Caption, cue.start / 1000, cue.end / 1000, &cue.text);
+    }
+    if options.gec {
+        correct_passages(indexer, Source::Caption);
+    }
+    return Ok(cues.len());
+}
+/// indexes embedded text subtitle streams and sidecar subtitle files, returns the number of cues
+fn ingest_subtitles(video_path: &Path, dump_path: &Path, indexer: &mut Indexer) -> usize {
+    let mut files = subtitles::find_sidecars(video_path);
+    match ffmpeg_utils::get_subtitle_streams(video_path) {
+        Ok(streams) => {
+            for stream in streams {
+                if !subtitles::is_text_codec(&stream.codec) {
+                    println!("Skipping {} subtitle stream, not text", stream.codec);
+                    continue;
+                }
+                let output_path = dump_path.join(format!("subtitles_{}.srt", stream.position));
+                match ffmpeg_utils::extract_subtitles(video_path, stream.position, &output_path) {
+                    FFmpegResult::Success(_) => {
+                        println!(
+                            "Extracted {} subtitle stream ({})",
+                            stream.language.as_deref().unwrap_or("unknown"),
+                            stream.codec
+                        );
+                        files.push(output_path);
+                    }
+                    FFmpegResult::Failure(error) => {
+                        eprintln!("Failed to extract subtitle stream, reason: \n{}", error)
+                    }
+                }
+            }
+        }
+        Err(error) => log(match error {
+            FFprobeResult::Failure(error) => ExitCode::FFProbeError(error),
+            FFprobeResult::Success(_) => ExitCode::Success,
+        }),
+    }
+    let mut num_cues = 0;
+    for file in files {
+        match subtitles::parse_file(&file) {
+            Ok(cues) => {
+                for cue in &cues {
+                    indexer.record(
+                        Source::Subtitle,
+                        cue.start / 1000,
+                        cue.end / 1000,
+                        &cue.text,
+                    );
+                }
+                num_cues += cues.len();
+            }
+            Err(error) => eprintln!("Failed to read {}, reason: \n{}", file.display(), error),
+        }
+    }
+    return num_cues;
+}
+fn asr_audio(dump_path: &Path, mut indexer: Indexer, options: &Options) -> PipelineResult {
+    let mut engine = match asr::make_engine(&options.asr) {
+        Ok(engine) => engine,
+        Err(error) => return PipelineResult::Error(ExitCode::AsrError(error)),
+    };
+    let segments = match engine.transcribe(&dump_path.join("audio.wav")) {
+        Ok(segments) => segments,
+        Err(error) => return PipelineResult::Error(ExitCode::AsrError(error)),
+    };
+    indexer.metadata.language = Some(engine.language());
+    let cues = record_segments(&segments, &mut indexer);
+    if options.gec {
+        correct_passages(&mut indexer, Source::Asr);
+    }
+    let cues = subtitles::split_cues(
+        &cues,
+        options.subtitle_max_line,
+        options.subtitle_word_level,
+    );
+    if let Err(error) = subtitles::save(&cues, dump_path, "transcript") {
+        return PipelineResult::Error(ExitCode::SaveError(error.to_string()));
+    }
+    return PipelineResult::Success(indexer);
+}
+/// indexes the transcribed segments as `asr`, returns them as cues for the transcript
+fn record_segments(segments: &[Segment], indexer: &mut Indexer) -> Vec<Cue> {
+    let mut cues = Vec::new();
+    for segment in segments {
+        indexer.record(
+            Source::Asr,
+            segment.start / 1000,
+            segment.end / 1000,
+            &segment.text,
+        );
+        cues.push(Cue {
+            start: segment.start,
+            end: segment.end,
+            text: segment.text.trim().to_string(),
+        });
+    }
+    return cues;
+}
+/// indexes the grammar-corrected form of every passage of `source` next to its raw text
+fn correct_passages(indexer: &mut Indexer, source: Source) {
+    let passages = indexer.passage_texts(source);
+    let texts = passages.iter().map(|(_, _, text)| text.clone()).collect();
+    let corrected = gec::correct_all(texts, config::constants::NUM_THREADS as usize);
+    for ((start, i, _), corrected) in passages.into_iter().zip(corrected) {
+        indexer.record_correction(start, i, &corrected);
+    }
+}
+/// a frame waiting for OCR, extracted to disk or decoded in memory
+enum OcrInput {
+    File(PathBuf),
+    Image(DynamicImage),
+}
+fn ocr_dir(frames_path: &Path, fps: u64, indexer: Indexer, options: &Options) -> PipelineResult {
+    let shots = dedup_frames(frames_path, fps, options);
+    let shots = shots.into_iter().map(|shot| Shot {
+        frame: OcrInput::File(shot.frame),
+        start: shot.start,
+        end: shot.end,
+    });
+    return ocr_shots(
+        shots,
+        &frames_path.with_file_name("debug"),
+        indexer,
+        options,
+    );
+
+    /// sorts the frames by time and folds near-duplicates into the frame before them,
+    /// only the masked view of the frames is compared
+    fn dedup_frames(frames_path: &Path, fps: u64, options: &Options) -> Vec<Shot> {
+        let mut frames: Vec<(u64, PathBuf)> = std::fs::read_dir(frames_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            // anything that isn't a numbered frame is left out
+            .filter_map(|path| Some((get_timestamp(path.file_name()?.to_str()?, fps)?, path)))
+            .collect();
+        frames.sort();
+        let num_frames = frames.len();
+        let hashed = frames
+            .into_iter()
+            .filter_map(|(timestamp, path)| match image::open(&path) {
+                Ok(image) => Some((dedup::dhash(&options.mask.view(&image)), path, timestamp)),
+                Err(error) => {
+                    eprintln!("Failed to read {:?}, reason: \n{}", path, error);
+                    None
+                }
+            })
+            .collect();
+        // frames that failed to decode were reported above and are not counted
+        let (shots, skipped) = dedup::fold(hashed, options.dedup_threshold);
+        println!(
+            "Skipped {} of {} frames as near-duplicates...",
+            skipped, num_frames
+        );
+        return shots;
+    }
+    /// extracts timestamp from frame number (image name), `None` if it isn't a frame
+    fn get_timestamp(filename: &str, fps: u64) -> Option<u64> {
+        let num_part = filename.strip_suffix(".jpg")?;
+        let frame_number: u64 = num_part.parse::<u64>().ok()?;
+        // videos under 1 fps, as in extract_frames
+        return Some(frame_number / fps.max(1));
+    }
+}
+/// decodes the sampled frames straight from ffmpeg, no frames are written to disk
+fn ocr_stream(
+    video_path: &Path,
+    dump_path: &Path,
+    indexer: Indexer,
+    options: &Options,
+) -> PipelineResult {
+    // OCR only needs gray, debug frames are kept in color
+    let format = match options.debug_boxes {
+        true => PixelFormat::Rgb,
+        false => PixelFormat::Gray,
+    };
+    let frames = match VideoFramesIterator::new(video_path, options.sampling, format) {
+        Ok(frames) => frames,
+        Err(error) => return PipelineResult::Error(ExitCode::KeyframesError(error.to_string())),
+    };
+    let mask = options.mask.clone();
+    let mut frames = frames.map(move |frame| {
+        let timestamp = frame.pts as u64;
+        let image = frame.into_image();
+        (dedup::dhash(&mask.view(&image)), image, timestamp)
+    });
+    let mut folder = dedup::Folder::new(options.dedup_threshold);
+    let shots = std::iter::from_fn(move || {
+        for (hash, image, timestamp) in frames.by_ref() {
+            if let Some(shot) = folder.push(hash, OcrInput::Image(image), timestamp) {
+                return Some(shot);
+            }
+        }
+        let last = folder.finish();
+        if last.is_some() {
+            println!(
+                "Skipped {} of {} frames as near-duplicates...",
+                folder.skipped(),
+                folder.seen
+            );
+        }
+        return last;
+    });
+    return ocr_shots(shots, &dump_path.join("debug"), indexer, options);
+}
+/// recognizes the shots and indexes the results in time order
+fn ocr_shots(
+    shots: impl Iterator<Item = Shot<OcrInput>>,
+    debug_path: &Path,
+    indexer: Indexer,
+    options: &Options,
+) -> PipelineResult {
+    let results = match recognize_shots(shots, debug_path, options) {
+        Ok(results) => results,
+        Err(code) => return PipelineResult::Error(code),
+    };
+    let mut indexer = indexer;
+    for (start, end, result) in results {
+        match options.slide_diff {
+            true => indexer.record_ocr_diff(start, end, &result),
+            false => indexer.record_ocr(start, end, &result),
+        }
+    }
+    if options.gec && !options.ocr.code {
+        correct_passages(&mut indexer, Source::Ocr);
+    }
+    let overlays = indexer.suppress_overlays(
+        options.overlay_share,
+        options.overlay_fixed_share,
+        options.overlay_mode,
+    );
+    if !overlays.is_empty() {
+        let words: Vec<&str> = overlays.iter().map(|o| o.word.as_str()).collect();
+        println!("Static overlays: {}", words.join(", "));
     }
     return PipelineResult::Success(indexer);
 }
-fn ocr_dir(frames_path: &Path, fps: u64, indexer: Indexer) -> PipelineResult {
+/// runs OCR on every shot in a thread pool, each thread borrows an engine from a pool.
+/// returns (start, end, result) in time order, frames without any text are left out
+fn recognize_shots(
+    shots: impl Iterator<Item = Shot<OcrInput>>,
+    debug_path: &Path,
+    options: &Options,
+) -> Result<Vec<(u64, u64, OcrResult)>, ExitCode> {
     const NUM_THREADS: usize = config::constants::NUM_THREADS as usize;
     let thread_pool = ThreadPool::new(NUM_THREADS);
-    let apis_pool_arc = Arc::new(Mutex::new(make_apis_pool(NUM_THREADS)));
-    let indexer_arc = Arc::new(Mutex::new(indexer));
+    let apis_pool = make_apis_pool(NUM_THREADS, options).map_err(ExitCode::OcrError)?;
+    let apis_pool_arc = Arc::new(Mutex::new(apis_pool));
+    // indexed after all frames are read, slide diffing needs them in time order
+    let results_arc = Arc::new(Mutex::new(Vec::new()));
+    // frames the text detection found nothing in
+    let textless_arc = Arc::new(AtomicUsize::new(0));
+    if options.debug_boxes {
+        std::fs::create_dir_all(debug_path).expect("Failed to create debug directory");
+    }
     ///////////////////////////////
-    let images = std::fs::read_dir(frames_path).unwrap();
-    for entry in images {
-        let entry = entry.unwrap();
-        let file_name = entry.file_name();
-        let timestamp = get_timestamp(file_name.to_str().unwrap(), fps);
+    for (i, shot) in shots.enumerate() {
+        // decoded frames would pile up in memory if OCR can't keep up
+        while thread_pool.queued_count() >= NUM_THREADS {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let debug_path = options.debug_boxes.then(|| match &shot.frame {
+            OcrInput::File(path) => debug_path.join(path.file_name().unwrap()),
+            OcrInput::Image(_) => debug_path.join(format!("{:06}.jpg", i)),
+        });
         // get references
         let apis = apis_pool_arc.clone();
-        let index = indexer_arc.clone();
+        let results = results_arc.clone();
+        let pipeline = options.preprocess.clone();
+        let mask = options.mask.clone();
+        let detect_text = options.detect_text;
+        let code = options.ocr.code;
+        let textless = textless_arc.clone();
         thread_pool.execute(move || {
             // pull an api from the apis pool and let go of the lock
             let mut apis_pool = apis.lock().unwrap();
             let mut api = apis_pool.pop().unwrap();
             drop(apis_pool);
             ///////////////////////////////
-            let text = ocr::threaded_ocr(entry.path().to_str().unwrap(), &mut api);
+            let result = match detect_text || !mask.is_empty() {
+                true => recognize_areas(api.as_mut(), &shot.frame, &mask, detect_text, &pipeline),
+                false => recognize_frame(api.as_mut(), &shot.frame, &pipeline).map(Some),
+            };
             // put an api back in the pool and let go of the lock
             let mut apis_pool = apis.lock().unwrap();
             apis_pool.push(api);
             drop(apis_pool);
             ///////////////////////////////
-            // let text = gec::correct(&text);
-            let mut index_lock = index.lock().unwrap();
-            index_lock.update(&text, timestamp);
+            let result = match result {
+                Ok(None) => {
+                    textless.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                // nothing was recognized, don't keep an empty passage
+                Ok(Some(result)) if result.words.is_empty() => return,
+                Ok(Some(mut result)) => {
+                    if code {
+                        result.indent_lines();
+                    }
+                    result
+                }
+                Err(error) => {
+                    eprintln!("OCR failed for frame {}, reason: \n{}", i, error);
+                    return;
+                }
+            };
+            if let Some(debug_path) = debug_path {
+                let saved = match &shot.frame {
+                    OcrInput::File(path) => draw::save_boxes(path, &result, &debug_path),
+                    OcrInput::Image(image) => draw::draw_boxes(image, &result).save(&debug_path),
+                };
+                if let Err(error) = saved {
+                    eprintln!("Failed to save debug frame, reason: \n{}", error);
+                }
+            }
+            results.lock().unwrap().push((shot.start, shot.end, result));
         });
     }
     thread_pool.join();
-    let mut res = indexer_arc.lock().unwrap();
-    let res = std::mem::replace(&mut *res, Indexer::new());
-    return PipelineResult::Success(res);
+    if options.detect_text {
+        println!(
+            "Skipped {} frames without text...",
+            textless_arc.load(Ordering::Relaxed)
+        );
+    }
+    let mut results = std::mem::take(&mut *results_arc.lock().unwrap());
+    results.sort_by_key(|(start, _, _)| *start);
+    return Ok(results);
 
-    fn make_apis_pool(num_threads: usize) -> Vec<leptess::LepTess> {
-        let mut apis_pool: Vec<leptess::LepTess> = Vec::new();
+    fn make_apis_pool(
+        num_threads: usize,
+        options: &Options,
+    ) -> Result<Vec<Box<dyn OcrEngine>>, String> {
+        let mut apis_pool: Vec<Box<dyn OcrEngine>> = Vec::new();
         for _ in 0..num_threads {
-            apis_pool.push(ocr::get_api());
+            apis_pool.push(ensemble::make_engine(options)?);
         }
-        return apis_pool;
+        return Ok(apis_pool);
     }
-    /// extracts timestamp from frame number (image name)
-    fn get_timestamp(filename: &str, fps: u64) -> u64 {
-        let num_part = filename.trim_end_matches(".jpg");
-        let frame_number: u64 = num_part.parse::<u64>().expect("Not a valid u64");
-        return frame_number / fps;
+    /// runs the frame through the pipeline if there is one, boxes are mapped back to the original frame
+    fn recognize_frame(
+        api: &mut dyn OcrEngine,
+        input: &OcrInput,
+        pipeline: &Pipeline,
+    ) -> Result<OcrResult, String> {
+        let image = match input {
+            OcrInput::File(path) if pipeline.is_empty() => return api.recognize(path),
+            OcrInput::Image(image) if pipeline.is_empty() => return api.recognize_image(image),
+            OcrInput::File(path) => image::open(path).map_err(|e| e.to_string())?,
+            OcrInput::Image(image) => image.clone(),
+        };
+        let (width, height) = (image.width(), image.height());
+        let mut result = api.recognize_image(&pipeline.apply(image))?;
+        result.scale_to(width, height);
+        return Ok(result);
     }
-}
-#[allow(unused)]
-fn iterate_frames(video_path: &Path) -> ExitCode {
-    let fi = VideoFramesIterator::new(video_path).unwrap();
-    match ffmpeg_utils::get_video_dims(video_path) {
-        Ok((width, height)) => {
-            println!("Dimensions: {}x{}", width, height);
-            for (i, frame) in fi.enumerate() {}
-            return ExitCode::Success;
+    /// only recognizes the areas of interest, with the excluded areas painted over.
+    /// with `detect_text`, only the regions inside them that look like text, `None` if there are none
+    fn recognize_areas(
+        api: &mut dyn OcrEngine,
+        input: &OcrInput,
+        mask: &Mask,
+        detect_text: bool,
+        pipeline: &Pipeline,
+    ) -> Result<Option<OcrResult>, String> {
+        let image = match input {
+            OcrInput::File(path) => image::open(path).map_err(|e| e.to_string())?,
+            OcrInput::Image(image) => image.clone(),
+        };
+        let image = mask.blank(image);
+        let mut regions = Vec::new();
+        for area in mask.areas(image.width(), image.height()) {
+            if !detect_text {
+                regions.push(area);
+                continue;
+            }
+            let crop = image.crop_imm(area.left, area.top, area.width, area.height);
+            regions.extend(
+                textdetect::text_regions(&crop)
+                    .into_iter()
+                    .map(|region| BoundingBox {
+                        left: area.left + region.left,
+                        top: area.top + region.top,
+                        ..region
+                    }),
+            );
         }
-        Err(error) => match error {
-            FFprobeResult::Failure(error) => return ExitCode::FFProbeError(error),
-            _ => return ExitCode::Success,
-        },
+        if regions.is_empty() {
+            return Ok(None);
+        }
+        let mut parts = Vec::new();
+        for region in regions {
+            let crop = image.crop_imm(region.left, region.top, region.width, region.height);
+            let crop = OcrInput::Image(crop);
+            parts.push((
+                recognize_frame(api, &crop, pipeline)?,
+                region.left,
+                region.top,
+            ));
+        }
+        return Ok(Some(OcrResult::merge(parts, image.width(), image.height())));
     }
 }
@@ -0,0 +1,146 @@
+use std::str::FromStr;
+
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+
+use crate::indexer::Region;
+use crate::ocr::BoundingBox;
+use crate::query;
+
+/// a rectangle of the frame, in pixels or relative to the frame size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    Pixels(BoundingBox),
+    Relative(Region),
+}
+
+impl FromStr for Area {
+    type Err = String;
+    /// `x,y,w,hpx` in pixels, otherwise anything `region:` takes in a query,
+    /// e.g. `top` or `0.1,0.05,0.8,0.75`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let Some(pixels) = s.strip_suffix("px") else {
+            return query::parse_region(s).map(Area::Relative);
+        };
+        let values: Vec<u32> = pixels
+            .split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid area '{}'", s))?;
+        match values[..] {
+            [left, top, width, height] => Ok(Area::Pixels(BoundingBox {
+                left,
+                top,
+                width,
+                height,
+            })),
+            _ => Err(format!("invalid area '{}', expected x,y,w,hpx", s)),
+        }
+    }
+}
+
+impl Area {
+    /// the area in pixels of a `width` x `height` frame, clipped to it
+    pub fn to_bbox(self, width: u32, height: u32) -> BoundingBox {
+        let (left, top, w, h) = match self {
+            Area::Pixels(bbox) => (bbox.left, bbox.top, bbox.width, bbox.height),
+            Area::Relative(region) => (
+                (region.x * width as f32).round() as u32,
+                (region.y * height as f32).round() as u32,
+                (region.w * width as f32).round() as u32,
+                (region.h * height as f32).round() as u32,
+            ),
+        };
+        let (left, top) = (left.min(width), top.min(height));
+        return BoundingBox {
+            left,
+            top,
+            width: w.min(width - left),
+            height: h.min(height - top),
+        };
+    }
+}
+
+/// parses areas separated by `;`
+pub fn parse_areas(s: &str) -> Result<Vec<Area>, String> {
+    return s
+        .split(';')
+        .filter(|area| !area.trim().is_empty())
+        .map(str::parse)
+        .collect();
+}
+
+/// the parts of every frame OCR looks at, e.g. the projector of a lecture capture,
+/// and the parts it never looks at, e.g. the chat panel of a stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mask {
+    /// areas of interest, the whole frame if there are none
+    pub include: Vec<Area>,
+    /// areas painted over before OCR
+    pub exclude: Vec<Area>,
+}
+
+impl Mask {
+    pub fn is_empty(&self) -> bool {
+        return self.include.is_empty() && self.exclude.is_empty();
+    }
+    /// the areas of interest in pixels, empty ones are left out
+    pub fn areas(&self, width: u32, height: u32) -> Vec<BoundingBox> {
+        if self.include.is_empty() {
+            return vec![BoundingBox {
+                left: 0,
+                top: 0,
+                width,
+                height,
+            }];
+        }
+        return self
+            .include
+            .iter()
+            .map(|area| area.to_bbox(width, height))
+            .filter(|bbox| bbox.width > 0 && bbox.height > 0)
+            .collect();
+    }
+    /// paints every excluded area in its mean color, so it has no edges and no text left
+    pub fn blank(&self, mut frame: DynamicImage) -> DynamicImage {
+        let (width, height) = frame.dimensions();
+        for area in &self.exclude {
+            let bbox = area.to_bbox(width, height);
+            let pixels = (bbox.width * bbox.height) as u64;
+            if pixels == 0 {
+                continue;
+            }
+            let mut sum = [0u64; 4];
+            for (_, _, pixel) in frame
+                .view(bbox.left, bbox.top, bbox.width, bbox.height)
+                .pixels()
+            {
+                for (s, c) in sum.iter_mut().zip(pixel.0) {
+                    *s += c as u64;
+                }
+            }
+            let mean = Rgba(sum.map(|s| (s / pixels) as u8));
+            for y in bbox.top..bbox.top + bbox.height {
+                for x in bbox.left..bbox.left + bbox.width {
+                    frame.put_pixel(x, y, mean);
+                }
+            }
+        }
+        return frame;
+    }
+    /// the part of the frame that matters for telling frames apart:
+    /// blanked, and cut to the box around the areas of interest
+    pub fn view(&self, frame: &DynamicImage) -> DynamicImage {
+        let frame = self.blank(frame.clone());
+        let areas = self.areas(frame.width(), frame.height());
+        let (Some(left), Some(top)) = (
+            areas.iter().map(|a| a.left).min(),
+            areas.iter().map(|a| a.top).min(),
+        ) else {
+            return frame;
+        };
+        let right = areas.iter().map(|a| a.left + a.width).max().unwrap_or(left);
+        let bottom = areas.iter().map(|a| a.top + a.height).max().unwrap_or(top);
+        return frame.crop_imm(left, top, right - left, bottom - top);
+    }
+}
@@ -1,71 +1,417 @@
-#![allow(unused)]
 use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+use std::thread;
 
-use leptess::LepTess;
-use rusty_tesseract::{tesseract as rt, Args};
-use tesseract::Tesseract;
-
-pub fn ocr(path: &str, api: &mut LepTess) -> String {
-    return ocr_from_disk(path, api);
-}
-
-pub fn get_api() -> LepTess {
-    let mut api = leptess::LepTess::new(Some("models/traineddata/tessdata_best"), "eng").unwrap();
-    api.set_variable(leptess::Variable::TesseditPagesegMode, "3")
-        .expect("Failed to set Leptess variable");
-    api.set_variable(leptess::Variable::TesseditOcrEngineMode, "2")
-        .expect("Failed to set Leptess variable");
-    api.set_variable(leptess::Variable::UserDefinedDpi, "100")
-        .expect("Failed to set Leptess variable");
-    return api;
-}
-pub fn get_tess_api() -> Tesseract {
-    let api = Tesseract::new(None, Some("eng"))
-        .unwrap()
-        .set_variable("tessedit_pageseg_mode", "12")
-        .expect("Failed to set Tesseract variable")
-        .set_variable("tessedit_ocr_engine_mode", "2")
-        .expect("Failed to set Tesseract variable");
-    // // .set_variable(leptess::Variable::UserDefinedDpi, "100")
-    // // .expect("Failed to set Tesseract variable");
-    return api;
-}
-
-fn ocr_from_disk(path: &str, api: &mut LepTess) -> String {
-    api.set_image(path).unwrap();
-    return api.get_utf8_text().expect("OCR failed");
-}
-
-pub fn threaded_ocr(path: &str, api: &mut LepTess) -> String {
-    api.set_image(path).unwrap();
-    return api.get_utf8_text().expect("Threaded OCR failed");
-}
-
-pub fn threaded_tess_ocr(path: &str, api: Tesseract) -> String {
-    return api
-        .set_image(path)
-        .unwrap()
-        .get_text()
-        .expect("Threaded OCR failed");
-}
-
-pub fn tess_ocr(path: &str, api: Tesseract) -> String {
-    return api.set_image(path).unwrap().get_text().expect("OCR failed");
-}
-
-pub fn rt_ocr(path: &str) -> String {
-    let image = &rusty_tesseract::Image::from_path(path).unwrap();
-    let args = Args {
-        lang: "eng".to_string(),
-        config_variables: HashMap::from([(
-            "TESSDATA_PREFIX".into(),
-            "models/traineddata/tessdata_fast/eng.traineddata".into(),
-        )]),
-        psm: 12,
-        oem: 2,
-        dpi: 100,
-    };
-    return rt::image_to_string(image, &args).unwrap();
+use image::DynamicImage;
+
+use crate::tessapi::TessApi;
+
+/// tesseract settings shared by every backend
+#[derive(Debug, Clone)]
+pub struct OcrSettings {
+    /// tessdata language(s), e.g. `eng` or `eng+deu`
+    pub lang: String,
+    pub psm: u32,
+    pub oem: u32,
+    pub dpi: u32,
+    /// directory containing the `.traineddata` files, tesseract's default if `None`
+    pub tessdata_dir: Option<String>,
+    /// read source code, see `CODE_VARIABLES`
+    pub code: bool,
+    /// files of extra dictionary words and patterns, written from the glossary
+    pub user_words: Option<String>,
+    pub user_patterns: Option<String>,
+}
+
+/// keeps runs of spaces and stops pushing identifiers towards dictionary words.
+/// unlike `load_system_dawg`, these can still be set after init
+const CODE_VARIABLES: [(&str, &str); 3] = [
+    ("preserve_interword_spaces", "1"),
+    ("language_model_penalty_non_freq_dict_word", "0"),
+    ("language_model_penalty_non_dict_word", "0"),
+];
+
+impl OcrSettings {
+    /// the tesseract variables besides the page segmentation mode and dpi,
+    /// every backend sets them before the language is loaded
+    pub fn variables(&self) -> Vec<(&'static str, String)> {
+        let mut variables = Vec::new();
+        if self.code {
+            variables.extend(CODE_VARIABLES.map(|(variable, value)| (variable, value.to_string())));
+        }
+        let files = [
+            ("user_words_file", &self.user_words),
+            ("user_patterns_file", &self.user_patterns),
+        ];
+        for (variable, path) in files {
+            if let Some(path) = path {
+                variables.push((variable, path.clone()));
+            }
+        }
+        return variables;
+    }
+}
+
+impl Default for OcrSettings {
+    fn default() -> Self {
+        return Self {
+            lang: "eng".to_string(),
+            psm: 3,
+            oem: 2,
+            dpi: 100,
+            tessdata_dir: Some("models/traineddata/tessdata_best".to_string()),
+            code: false,
+            user_words: None,
+            user_patterns: None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    LepTess,
+    /// kept for existing configurations, links tesseract like `LepTess`
+    Tesseract,
+    TesseractCli,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "leptess" => Ok(Backend::LepTess),
+            "tesseract" => Ok(Backend::Tesseract),
+            "tesseract-cli" | "tesseract_cli" => Ok(Backend::TesseractCli),
+            _ => Err(format!("unknown OCR backend '{}'", s)),
+        }
+    }
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::LepTess => "leptess",
+            Backend::Tesseract => "tesseract",
+            Backend::TesseractCli => "tesseract-cli",
+        }
+    }
+}
+
+/// pixel coordinates of a word in the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BoundingBox {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// a recognized word, `block`, `paragraph` and `line` locate it in the page layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrWord {
+    pub text: String,
+    /// 0-100, as reported by tesseract
+    pub confidence: f32,
+    pub block: u32,
+    pub paragraph: u32,
+    pub line: u32,
+    pub bbox: BoundingBox,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OcrResult {
+    /// the recognized words joined by line
+    pub text: String,
+    pub words: Vec<OcrWord>,
+    /// frame dimensions, the word boxes are relative to them
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OcrResult {
+    pub fn from_words(words: Vec<OcrWord>, width: u32, height: u32) -> Self {
+        let mut text = String::new();
+        let mut previous = None;
+        for word in &words {
+            let line = Some((word.block, word.paragraph, word.line));
+            if previous.is_some() {
+                text.push(if previous == line { ' ' } else { '\n' });
+            }
+            text.push_str(&word.text);
+            previous = line;
+        }
+        return Self {
+            text,
+            words,
+            width,
+            height,
+        };
+    }
+    /// builds a result from tesseract's TSV output,
+    /// the page row gives the frame dimensions and only word rows are kept
+    pub fn from_tsv(tsv: &str) -> Self {
+        let (mut width, mut height) = (0, 0);
+        let mut words = Vec::new();
+        for row in tsv.lines() {
+            let fields: Vec<&str> = row.split('\t').collect();
+            if fields.len() < 12 {
+                continue;
+            }
+            let number = |i: usize| fields[i].parse::<u32>().unwrap_or(0);
+            if fields[0] == "1" {
+                (width, height) = (number(8), number(9));
+            }
+            let text = fields[11].trim();
+            if fields[0] != "5" || text.is_empty() {
+                continue;
+            }
+            words.push(OcrWord {
+                text: text.to_string(),
+                confidence: fields[10].parse().unwrap_or(0.0),
+                block: number(2),
+                paragraph: number(3),
+                line: number(4),
+                bbox: BoundingBox {
+                    left: number(6),
+                    top: number(7),
+                    width: number(8),
+                    height: number(9),
+                },
+            });
+        }
+        return Self::from_words(words, width, height);
+    }
+    /// the words grouped by line, in reading order
+    pub fn lines(&self) -> Vec<&[OcrWord]> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for i in 1..=self.words.len() {
+            let key = |word: &OcrWord| (word.block, word.paragraph, word.line);
+            if i == self.words.len() || key(&self.words[i]) != key(&self.words[i - 1]) {
+                lines.push(&self.words[start..i]);
+                start = i;
+            }
+        }
+        return lines;
+    }
+    /// rebuilds the text with each line indented like on screen, in spaces of the median
+    /// character width, relative to the leftmost line of its block
+    pub fn indent_lines(&mut self) {
+        let mut char_widths: Vec<f32> = self
+            .words
+            .iter()
+            .map(|word| word.bbox.width as f32 / word.text.chars().count().max(1) as f32)
+            .collect();
+        if char_widths.is_empty() {
+            return;
+        }
+        char_widths.sort_by(f32::total_cmp);
+        let char_width = char_widths[char_widths.len() / 2].max(1.0);
+        let mut block_left: HashMap<u32, u32> = HashMap::new();
+        for word in &self.words {
+            let left = block_left.entry(word.block).or_insert(word.bbox.left);
+            *left = (*left).min(word.bbox.left);
+        }
+        let mut text = String::new();
+        let mut previous = None;
+        for word in &self.words {
+            let line = Some((word.block, word.paragraph, word.line));
+            if previous == line {
+                text.push(' ');
+            } else {
+                if previous.is_some() {
+                    text.push('\n');
+                }
+                let indent = (word.bbox.left - block_left[&word.block]) as f32 / char_width;
+                text.push_str(&" ".repeat(indent.round() as usize));
+            }
+            text.push_str(&word.text);
+            previous = line;
+        }
+        self.text = text;
+    }
+    /// joins the results of crops of a frame, given with the position of each crop
+    pub fn merge(parts: Vec<(OcrResult, u32, u32)>, width: u32, height: u32) -> Self {
+        let mut words = Vec::new();
+        // blocks are numbered per crop, renumber them so lines of different crops stay apart
+        let mut first_block = 0;
+        for (part, left, top) in parts {
+            let mut last_block = first_block;
+            for mut word in part.words {
+                word.block += first_block;
+                word.bbox.left += left;
+                word.bbox.top += top;
+                last_block = last_block.max(word.block);
+                words.push(word);
+            }
+            first_block = last_block + 1;
+        }
+        return Self::from_words(words, width, height);
+    }
+    /// maps the word boxes to a frame of a different size, e.g. the frame before upscaling
+    pub fn scale_to(&mut self, width: u32, height: u32) {
+        if self.width == 0 || self.height == 0 || (self.width, self.height) == (width, height) {
+            return;
+        }
+        let scale_x = |v: u32| (v as u64 * width as u64 / self.width as u64) as u32;
+        let scale_y = |v: u32| (v as u64 * height as u64 / self.height as u64) as u32;
+        for word in &mut self.words {
+            word.bbox = BoundingBox {
+                left: scale_x(word.bbox.left),
+                top: scale_y(word.bbox.top),
+                width: scale_x(word.bbox.width),
+                height: scale_y(word.bbox.height),
+            };
+        }
+        (self.width, self.height) = (width, height);
+    }
+}
+
+pub trait OcrEngine: Send {
+    fn recognize(&mut self, path: &Path) -> Result<OcrResult, String>;
+    /// recognizes a frame that is already in memory, e.g. after preprocessing
+    fn recognize_image(&mut self, image: &DynamicImage) -> Result<OcrResult, String>;
+}
+
+pub fn make_engine(backend: Backend, settings: &OcrSettings) -> Result<Box<dyn OcrEngine>, String> {
+    match backend {
+        Backend::LepTess | Backend::Tesseract => Ok(Box::new(LepTessEngine::new(settings)?)),
+        Backend::TesseractCli => Ok(Box::new(TesseractCliEngine::new(settings))),
+    }
+}
+
+/// links tesseract through leptess
+pub struct LepTessEngine {
+    api: TessApi,
+}
+
+impl LepTessEngine {
+    pub fn new(settings: &OcrSettings) -> Result<Self, String> {
+        let mut variables = vec![
+            ("tessedit_pageseg_mode", settings.psm.to_string()),
+            ("user_defined_dpi", settings.dpi.to_string()),
+        ];
+        variables.extend(settings.variables());
+        let api = TessApi::new(
+            settings.tessdata_dir.as_deref(),
+            &settings.lang,
+            settings.oem,
+            &variables,
+        )?;
+        return Ok(Self { api });
+    }
+}
+
+impl OcrEngine for LepTessEngine {
+    fn recognize(&mut self, path: &Path) -> Result<OcrResult, String> {
+        self.api.set_image_file(path)?;
+        return Ok(OcrResult::from_tsv(&self.api.get_tsv_text()?));
+    }
+    /// hands the raw pixels to tesseract, no encoding involved
+    fn recognize_image(&mut self, image: &DynamicImage) -> Result<OcrResult, String> {
+        let (width, height) = (image.width(), image.height());
+        match image {
+            DynamicImage::ImageLuma8(gray) => {
+                self.api.set_frame(gray.as_raw(), width, height, 1)?
+            }
+            image => self
+                .api
+                .set_frame(image.to_rgb8().as_raw(), width, height, 3)?,
+        }
+        return Ok(OcrResult::from_tsv(&self.api.get_tsv_text()?));
+    }
+}
+
+/// runs the tesseract executable, slower but needs no linking. every engine passes its own
+/// `--tessdata-dir`, so engines with different models can run side by side
+pub struct TesseractCliEngine {
+    args: Vec<String>,
+}
+
+impl TesseractCliEngine {
+    pub fn new(settings: &OcrSettings) -> Self {
+        let mut args: Vec<String> = vec![
+            "-l".to_string(),
+            settings.lang.clone(),
+            "--psm".to_string(),
+            settings.psm.to_string(),
+            "--oem".to_string(),
+            settings.oem.to_string(),
+            "--dpi".to_string(),
+            settings.dpi.to_string(),
+        ];
+        if let Some(tessdata_dir) = &settings.tessdata_dir {
+            args.extend(["--tessdata-dir".to_string(), tessdata_dir.clone()]);
+        }
+        for (variable, value) in settings.variables() {
+            args.extend(["-c".to_string(), format!("{}={}", variable, value)]);
+        }
+        return Self { args };
+    }
+    /// the command reading `path`, `stdin` for the standard input,
+    /// with the words as TSV on its output
+    pub fn command(&self, path: &Path) -> Command {
+        let mut command = Command::new("tesseract");
+        command
+            .arg(path)
+            .arg("stdout")
+            .args(&self.args)
+            // the config file, it has to come last
+            .arg("tsv");
+        return command;
+    }
+    fn parse_output(output: Output) -> Result<OcrResult, String> {
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+        return Ok(OcrResult::from_tsv(&String::from_utf8_lossy(
+            &output.stdout,
+        )));
+    }
+}
+
+impl OcrEngine for TesseractCliEngine {
+    fn recognize(&mut self, path: &Path) -> Result<OcrResult, String> {
+        let output = self
+            .command(path)
+            .output()
+            .map_err(|e| format!("failed to run tesseract: {}", e))?;
+        return Self::parse_output(output);
+    }
+    /// pipes the raw pixels to the executable as a binary PNM, no encoding involved
+    fn recognize_image(&mut self, image: &DynamicImage) -> Result<OcrResult, String> {
+        let (width, height) = (image.width(), image.height());
+        let mut pnm = Vec::new();
+        match image {
+            DynamicImage::ImageLuma8(gray) => {
+                pnm.extend(format!("P5\n{} {}\n255\n", width, height).bytes());
+                pnm.extend_from_slice(gray.as_raw());
+            }
+            image => {
+                pnm.extend(format!("P6\n{} {}\n255\n", width, height).bytes());
+                pnm.extend_from_slice(image.to_rgb8().as_raw());
+            }
+        }
+        let mut child = self
+            .command(Path::new("stdin"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run tesseract: {}", e))?;
+        // written from another thread, tesseract may fill its output pipe before reading it all
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&pnm));
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        writer
+            .join()
+            .unwrap()
+            .map_err(|e| format!("failed to write the frame: {}", e))?;
+        return Self::parse_output(output);
+    }
 }
 // enum OcrEngineMode {
 //     OEM_TESSERACT_ONLY = 0
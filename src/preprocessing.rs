@@ -1,11 +1,281 @@
-// use image::DynamicImage;
-
-// pub fn gray_img(image: DynamicImage) -> DynamicImage {
-//     return image.grayscale();
-// }
-// pub fn scale_img(image: DynamicImage) -> DynamicImage {
-//     return image.resize(256, 256, image::imageops::FilterType::CatmullRom);
-// }
-// pub fn blur_img(image: DynamicImage) -> DynamicImage {
-//     return image.blur(2.0);
-// }
+use std::str::FromStr;
+
+use image::{imageops, DynamicImage, GrayImage, Luma};
+
+/// frames are never upscaled by more than this factor
+const MAX_UPSCALE: f32 = 4.0;
+/// subtracted from the local mean in adaptive binarization
+const ADAPTIVE_OFFSET: i32 = 7;
+/// deskew searches angles in [-MAX_SKEW, MAX_SKEW] degrees
+const MAX_SKEW: f32 = 5.0;
+const SKEW_STEP: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Grayscale,
+    /// upscales frames shorter than the given height, small text is read poorly
+    Upscale(u32),
+    /// global binarization with Otsu's threshold
+    Otsu,
+    /// binarization against the mean of a window of the given radius
+    Adaptive(u32),
+    /// inverts light-on-dark frames so text is always dark on light
+    Invert,
+    /// 3x3 median filter
+    Denoise,
+    /// rotates the frame so text lines are horizontal
+    Deskew,
+}
+
+impl FromStr for Step {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.trim().split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s.trim(), None),
+        };
+        let arg = |default: u32| match arg {
+            Some(arg) => arg.parse::<u32>().map_err(|_| {
+                format!(
+                    "invalid argument '{}' for preprocessing step '{}'",
+                    arg, name
+                )
+            }),
+            None => Ok(default),
+        };
+        match name {
+            "gray" | "grayscale" => Ok(Step::Grayscale),
+            "upscale" => Ok(Step::Upscale(arg(1080)?)),
+            "otsu" => Ok(Step::Otsu),
+            "adaptive" => Ok(Step::Adaptive(arg(15)?)),
+            "invert" => Ok(Step::Invert),
+            "denoise" => Ok(Step::Denoise),
+            "deskew" => Ok(Step::Deskew),
+            _ => Err(format!("unknown preprocessing step '{}'", name)),
+        }
+    }
+}
+
+/// the chain of steps applied to a frame before OCR, e.g. `gray,upscale:720,otsu,invert`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pipeline {
+    pub steps: Vec<Step>,
+}
+
+impl FromStr for Pipeline {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() || s.trim() == "none" {
+            return Ok(Pipeline::default());
+        }
+        let steps = s.split(',').map(Step::from_str).collect::<Result<_, _>>()?;
+        return Ok(Pipeline { steps });
+    }
+}
+
+impl Pipeline {
+    pub fn is_empty(&self) -> bool {
+        return self.steps.is_empty();
+    }
+    pub fn apply(&self, mut image: DynamicImage) -> DynamicImage {
+        for step in &self.steps {
+            image = match step {
+                Step::Grayscale => image.grayscale(),
+                Step::Upscale(min_height) => upscale(image, *min_height),
+                Step::Otsu => {
+                    let gray = image.to_luma8();
+                    let threshold = otsu_threshold(&gray);
+                    DynamicImage::ImageLuma8(binarize(&gray, |_, _| threshold))
+                }
+                Step::Adaptive(radius) => {
+                    DynamicImage::ImageLuma8(adaptive(&image.to_luma8(), *radius))
+                }
+                Step::Invert => {
+                    let mut gray = image.to_luma8();
+                    if is_dark(&gray) {
+                        imageops::invert(&mut gray);
+                    }
+                    DynamicImage::ImageLuma8(gray)
+                }
+                Step::Denoise => DynamicImage::ImageLuma8(median3x3(&image.to_luma8())),
+                Step::Deskew => {
+                    let gray = image.to_luma8();
+                    let angle = skew_angle(&gray);
+                    DynamicImage::ImageLuma8(rotate(&gray, -angle))
+                }
+            };
+        }
+        return image;
+    }
+}
+
+fn upscale(image: DynamicImage, min_height: u32) -> DynamicImage {
+    let height = image.height().max(1);
+    if height >= min_height {
+        return image;
+    }
+    let factor = (min_height as f32 / height as f32).min(MAX_UPSCALE);
+    let width = (image.width() as f32 * factor).round() as u32;
+    let height = (height as f32 * factor).round() as u32;
+    return image.resize_exact(width, height, imageops::FilterType::CatmullRom);
+}
+
+fn binarize(gray: &GrayImage, threshold: impl Fn(u32, u32) -> u8) -> GrayImage {
+    return GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        match gray.get_pixel(x, y)[0] > threshold(x, y) {
+            true => Luma([255]),
+            false => Luma([0]),
+        }
+    });
+}
+
+/// the threshold maximizing the between-class variance of the histogram
+pub fn otsu_threshold(gray: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total = gray.pixels().len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum();
+    let (mut background_sum, mut background_weight) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (value, count) in histogram.iter().enumerate() {
+        background_weight += *count as f64;
+        if background_weight == 0.0 {
+            continue;
+        }
+        let foreground_weight = total - background_weight;
+        if foreground_weight == 0.0 {
+            break;
+        }
+        background_sum += value as f64 * *count as f64;
+        let background_mean = background_sum / background_weight;
+        let foreground_mean = (sum - background_sum) / foreground_weight;
+        let variance =
+            background_weight * foreground_weight * (background_mean - foreground_mean).powi(2);
+        if variance > best_variance {
+            (best, best_variance) = (value, variance);
+        }
+    }
+    return best as u8;
+}
+
+fn adaptive(gray: &GrayImage, radius: u32) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    // summed area table with a zero row and column in front
+    let stride = width as usize + 1;
+    let mut integral = vec![0u64; stride * (height as usize + 1)];
+    for y in 0..height as usize {
+        let mut row_sum = 0;
+        for x in 0..width as usize {
+            row_sum += gray.get_pixel(x as u32, y as u32)[0] as u64;
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row_sum;
+        }
+    }
+    return binarize(gray, |x, y| {
+        let (x0, y0) = (
+            x.saturating_sub(radius) as usize,
+            y.saturating_sub(radius) as usize,
+        );
+        let x1 = (x + radius + 1).min(width) as usize;
+        let y1 = (y + radius + 1).min(height) as usize;
+        let sum = integral[y1 * stride + x1] + integral[y0 * stride + x0]
+            - integral[y0 * stride + x1]
+            - integral[y1 * stride + x0];
+        let mean = sum / ((x1 - x0) * (y1 - y0)) as u64;
+        (mean as i32 - ADAPTIVE_OFFSET).clamp(0, 255) as u8
+    });
+}
+
+/// true if most of the frame is darker than mid gray, i.e. light text on a dark background
+pub fn is_dark(gray: &GrayImage) -> bool {
+    let total: u64 = gray.pixels().map(|p| p[0] as u64).sum();
+    return total < 128 * gray.pixels().len() as u64;
+}
+
+fn median3x3(gray: &GrayImage) -> GrayImage {
+    let (width, height) = gray.dimensions();
+    return GrayImage::from_fn(width, height, |x, y| {
+        let mut window = [0u8; 9];
+        let mut i = 0;
+        for dy in [-1i64, 0, 1] {
+            for dx in [-1i64, 0, 1] {
+                let nx = (x as i64 + dx).clamp(0, width as i64 - 1) as u32;
+                let ny = (y as i64 + dy).clamp(0, height as i64 - 1) as u32;
+                window[i] = gray.get_pixel(nx, ny)[0];
+                i += 1;
+            }
+        }
+        window.sort_unstable();
+        Luma([window[4]])
+    });
+}
+
+/// estimates the text angle in degrees by maximizing the variance of the row profile
+/// of dark pixels, measured on a downscaled copy of the frame
+pub fn skew_angle(gray: &GrayImage) -> f32 {
+    let small = match gray.width() > 400 {
+        true => imageops::resize(
+            gray,
+            400,
+            (gray.height() * 400 / gray.width()).max(1),
+            imageops::FilterType::Triangle,
+        ),
+        false => gray.clone(),
+    };
+    let threshold = otsu_threshold(&small);
+    let dark_is_text = !is_dark(&small);
+    let points: Vec<(f32, f32)> = small
+        .enumerate_pixels()
+        .filter(|(_, _, p)| (p[0] <= threshold) == dark_is_text)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    if points.is_empty() {
+        return 0.0;
+    }
+    let rows = (small.width() + small.height()) as usize * 2;
+    let (mut best_angle, mut best_score) = (0.0, f64::MIN);
+    let mut angle = -MAX_SKEW;
+    while angle <= MAX_SKEW {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut profile = vec![0u32; rows];
+        for (x, y) in &points {
+            let row = (y * cos - x * sin + rows as f32 / 2.0) as usize;
+            profile[row.min(rows - 1)] += 1;
+        }
+        let score: f64 = profile.iter().map(|c| (*c as f64).powi(2)).sum();
+        // prefer the smallest rotation on ties
+        if score > best_score || (score == best_score && angle.abs() < f32::abs(best_angle)) {
+            (best_angle, best_score) = (angle, score);
+        }
+        angle += SKEW_STEP;
+    }
+    return best_angle;
+}
+
+/// rotates around the center by `angle` degrees, uncovered pixels are filled with the border color
+fn rotate(gray: &GrayImage, angle: f32) -> GrayImage {
+    if angle == 0.0 {
+        return gray.clone();
+    }
+    let (width, height) = gray.dimensions();
+    let fill = match is_dark(gray) {
+        true => Luma([0]),
+        false => Luma([255]),
+    };
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+    return GrayImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+        let sx = (dx * cos + dy * sin + cx).round();
+        let sy = (-dx * sin + dy * cos + cy).round();
+        if sx < 0.0 || sy < 0.0 || sx >= width as f32 || sy >= height as f32 {
+            return fill;
+        }
+        *gray.get_pixel(sx as u32, sy as u32)
+    });
+}
@@ -0,0 +1,67 @@
+use crate::indexer::Region;
+
+/// a parsed search query, fields like `region:top` are taken out of the free text
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub text: String,
+    /// only match OCR words whose center lies in this part of the frame
+    pub region: Option<Region>,
+    /// words given as `title:word`, only matched in large or top-of-frame text
+    pub title: String,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let mut parsed = Query::default();
+        let mut words = Vec::new();
+        let mut title = Vec::new();
+        for word in query.split_whitespace() {
+            match word.split_once(':') {
+                Some(("region", region)) => parsed.region = Some(parse_region(region)?),
+                Some(("title", word)) if !word.is_empty() => title.push(word),
+                _ => words.push(word),
+            }
+        }
+        parsed.text = words.join(" ");
+        parsed.title = title.join(" ");
+        return Ok(parsed);
+    }
+    /// the free text and the title words together
+    pub fn all_text(&self) -> String {
+        return format!("{} {}", self.text, self.title).trim().to_string();
+    }
+}
+
+/// a named third of the frame (`top`, `middle`, `bottom`, `left`, `center`, `right`)
+/// or relative `x,y,w,h` coordinates
+pub fn parse_region(region: &str) -> Result<Region, String> {
+    const THIRD: f32 = 1.0 / 3.0;
+    let named = match region {
+        "top" => Some((0.0, 0.0, 1.0, THIRD)),
+        "middle" => Some((0.0, THIRD, 1.0, THIRD)),
+        "bottom" => Some((0.0, 2.0 * THIRD, 1.0, THIRD)),
+        "left" => Some((0.0, 0.0, THIRD, 1.0)),
+        "center" => Some((THIRD, 0.0, THIRD, 1.0)),
+        "right" => Some((2.0 * THIRD, 0.0, THIRD, 1.0)),
+        _ => None,
+    };
+    if let Some((x, y, w, h)) = named {
+        return Ok(Region { x, y, w, h });
+    }
+    let values: Vec<f32> = region
+        .split(',')
+        .map(|v| v.parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid region '{}'", region))?;
+    match values[..] {
+        [x, y, w, h] if values.iter().all(|v| (0.0..=1.0).contains(v)) => {
+            return Ok(Region { x, y, w, h });
+        }
+        _ => {
+            return Err(format!(
+                "invalid region '{}', expected top, middle, bottom, left, center, right or x,y,w,h between 0 and 1",
+                region
+            ))
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::config::Options;
+use crate::ensemble;
+use crate::indexer::{Hit, Indexer};
+use crate::mask::Mask;
+use crate::ocr::OcrEngine;
+use crate::vidsplicer::ffmpeg_utils::{self, FFprobeResult};
+
+/// when the matched text really came on and left the screen, in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Refined {
+    pub start: f64,
+    pub end: f64,
+}
+
+/// narrows OCR hits down to the frame: the text of a hit appeared somewhere between the
+/// sampled frame before it and the frame it was found in, so the frames in between are
+/// decoded one by one and read, in a binary search
+pub struct Refiner {
+    video_path: PathBuf,
+    fps: u64,
+    /// number of frames in the video
+    frames: u64,
+    engine: Box<dyn OcrEngine>,
+    mask: Mask,
+}
+
+impl Refiner {
+    pub fn new(video_path: &Path, options: &Options) -> Result<Self, String> {
+        let metadata = match ffmpeg_utils::get_video_metadata(video_path) {
+            FFprobeResult::Success(metadata) => metadata,
+            FFprobeResult::Failure(error) => return Err(error),
+        };
+        let fps = metadata.fps.max(1);
+        return Ok(Self {
+            video_path: video_path.to_path_buf(),
+            fps,
+            frames: (metadata.duration * fps as f64) as u64,
+            engine: ensemble::make_engine(options)?,
+            mask: options.mask.clone(),
+        });
+    }
+    /// the in and out time of a hit, `None` for hits that weren't on screen.
+    /// hit times are whole seconds, so the search starts a second early and ends a second late
+    pub fn refine(
+        &mut self,
+        indexer: &Indexer,
+        hit: &Hit,
+        terms: &HashSet<String>,
+    ) -> Result<Option<Refined>, String> {
+        let Some(end) = hit.end else {
+            return Ok(None);
+        };
+        let (before, after) = indexer.frames_around(hit.timestamp, end);
+        // the probed duration can be short of the last frame
+        let last_frame = self.frames.saturating_sub(1).max((end + 1) * self.fps - 1);
+        let first = first_visible(
+            before.map_or(0, |before| before * self.fps),
+            ((hit.timestamp + 1) * self.fps - 1).min(last_frame),
+            |frame| self.shows(frame, indexer, terms),
+        )?;
+        let last = last_visible(
+            (end * self.fps).max(first),
+            after.map_or(last_frame, |after| {
+                ((after + 1) * self.fps - 1).min(last_frame)
+            }),
+            |frame| self.shows(frame, indexer, terms),
+        )?;
+        return Ok(Some(Refined {
+            start: first as f64 / self.fps as f64,
+            // until the next frame comes on
+            end: (last + 1) as f64 / self.fps as f64,
+        }));
+    }
+    /// true if all terms can be read in the frame
+    fn shows(
+        &mut self,
+        frame: u64,
+        indexer: &Indexer,
+        terms: &HashSet<String>,
+    ) -> Result<bool, String> {
+        let image = ffmpeg_utils::frame_at(&self.video_path, frame as f64 / self.fps as f64)?;
+        let result = self.engine.recognize_image(&self.mask.blank(image))?;
+        let words = indexer.tokenize(&result.text);
+        return Ok(terms.iter().all(|term| words.contains(term)));
+    }
+}
+
+/// the first frame in `from..=to` that shows the text, assuming it stays once it is shown.
+/// `to` if none before it does
+pub fn first_visible(
+    from: u64,
+    to: u64,
+    mut shows: impl FnMut(u64) -> Result<bool, String>,
+) -> Result<u64, String> {
+    let (mut low, mut high) = (from.min(to), to);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match shows(middle)? {
+            true => high = middle,
+            false => low = middle + 1,
+        }
+    }
+    return Ok(high);
+}
+
+/// the last frame in `from..=to` that shows the text, assuming it doesn't come back once gone.
+/// `from` if none after it does
+pub fn last_visible(
+    from: u64,
+    to: u64,
+    mut shows: impl FnMut(u64) -> Result<bool, String>,
+) -> Result<u64, String> {
+    let (mut low, mut high) = (from, to.max(from));
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        match shows(middle)? {
+            true => low = middle,
+            false => high = middle - 1,
+        }
+    }
+    return Ok(low);
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::json;
+use threadpool::ThreadPool;
+
+use crate::config::{constants::NUM_THREADS, Options};
+use crate::indexer::Indexer;
+use crate::query::Query;
+use crate::{disk, format_timestamp, parse_timestamp, ExitCode};
+
+const PLAYER_HTML: &str = include_str!("player.html");
+
+pub struct Video {
+    pub name: String,
+    pub indexer: Indexer,
+}
+
+/// the set of indexes served by `vcf serve`, a video's id is its position in `videos`
+pub struct Library {
+    pub videos: Vec<Video>,
+}
+
+impl Library {
+    /// loads `index.json` from every dump directory
+    pub fn load(dump_dirs: &[String]) -> Result<Self, ExitCode> {
+        let mut videos = Vec::new();
+        for dir in dump_dirs {
+            let dir = PathBuf::from(dir);
+            let indexer = disk::load_index(&dir.join("index.json"))
+                .map_err(|e| ExitCode::LoadError(format!("{}: {}", dir.display(), e)))?;
+            let name = match &indexer.metadata.video_path {
+                Some(path) => path.file_name().unwrap_or_default(),
+                None => dir.file_name().unwrap_or_default(),
+            };
+            videos.push(Video {
+                name: name.to_string_lossy().to_string(),
+                indexer,
+            });
+        }
+        return Ok(Self { videos });
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+}
+
+/// starts the search server over the given dump directories, blocks forever
+pub fn serve(dump_dirs: &[String], options: &Options) -> Result<(), ExitCode> {
+    if dump_dirs.is_empty() {
+        return Err(ExitCode::InvalidArgs);
+    }
+    let library = Library::load(dump_dirs)?;
+    let listener = TcpListener::bind(("127.0.0.1", options.port))
+        .map_err(|e| ExitCode::ServeError(e.to_string()))?;
+    let address = listener
+        .local_addr()
+        .map_err(|e| ExitCode::ServeError(e.to_string()))?;
+    println!(
+        "Serving {} index(es) on http://{}",
+        library.videos.len(),
+        address
+    );
+    run(listener, Arc::new(library));
+    return Ok(());
+}
+
+/// accepts connections until the listener fails
+pub fn run(listener: TcpListener, library: Arc<Library>) {
+    let thread_pool = ThreadPool::new(NUM_THREADS as usize);
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let library = library.clone();
+        thread_pool.execute(move || {
+            let _ = handle(stream, &library);
+        });
+    }
+}
+
+fn handle(mut stream: TcpStream, library: &Library) -> io::Result<()> {
+    let request = match read_request(&stream) {
+        Ok(request) => request,
+        Err(_) => return respond(&mut stream, "400 Bad Request", "text/plain", b"bad request"),
+    };
+    if request.method != "GET" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"method not allowed",
+        );
+    }
+    match request.path.as_str() {
+        "/" => respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            PLAYER_HTML.as_bytes(),
+        ),
+        "/api/videos" => {
+            let videos: Vec<_> = library
+                .videos
+                .iter()
+                .enumerate()
+                .map(|(id, video)| json!({ "id": id, "name": video.name }))
+                .collect();
+            respond_json(&mut stream, &json!(videos))
+        }
+        "/api/search" => {
+            let query = request.query.get("q").cloned().unwrap_or_default();
+            let only = request
+                .query
+                .get("video")
+                .and_then(|id| id.parse::<usize>().ok());
+            match Query::parse(&query.trim().to_lowercase()) {
+                Ok(query) => respond_json(&mut stream, &search(library, &query, only)),
+                Err(error) => respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    error.as_bytes(),
+                ),
+            }
+        }
+        "/api/at" => {
+            let video = request
+                .query
+                .get("video")
+                .and_then(|id| id.parse::<usize>().ok())
+                .and_then(|id| library.videos.get(id));
+            let timestamp = request.query.get("t").and_then(|t| parse_timestamp(t));
+            match (video, timestamp) {
+                (Some(video), Some(timestamp)) => {
+                    let moment = video.indexer.at(timestamp);
+                    let body = json!({ "frame": moment.frame, "segments": moment.segments });
+                    respond_json(&mut stream, &body)
+                }
+                _ => respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    b"expected video=<id>&t=<HH:MM:SS>",
+                ),
+            }
+        }
+        path => match path
+            .strip_prefix("/video/")
+            .and_then(|id| id.parse::<usize>().ok())
+            .and_then(|id| library.videos.get(id))
+            .and_then(|video| video.indexer.metadata.video_path.as_ref())
+        {
+            Some(video_path) => stream_file(&mut stream, video_path, &request.headers),
+            None => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+        },
+    }
+}
+
+/// runs a query against every video (or only `only`), hits are grouped by video and ranked
+/// within each by their score, see `Indexer::search_hits`
+fn search(library: &Library, query: &Query, only: Option<usize>) -> serde_json::Value {
+    let mut hits = Vec::new();
+    for (id, video) in library.videos.iter().enumerate() {
+        if only.is_some_and(|only| only != id) {
+            continue;
+        }
+        for hit in video.indexer.search_hits(query) {
+            let snippets: Vec<_> = hit
+                .snippets
+                .iter()
+                .map(|snippet| {
+                    let parts: Vec<_> = snippet
+                        .parts()
+                        .iter()
+                        .map(|(text, matched)| json!({ "text": text, "match": matched }))
+                        .collect();
+                    json!({ "source": snippet.source, "parts": parts })
+                })
+                .collect();
+            hits.push(json!({
+                "video": id,
+                "name": video.name,
+                "timestamp": hit.timestamp,
+                "time": format_timestamp(hit.timestamp),
+                "snippets": snippets,
+                "regions": hit.regions,
+                "score": hit.score,
+            }));
+        }
+    }
+    return json!(hits);
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "malformed request line",
+        ));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (decode_component(key), decode_component(value)))
+        .collect();
+
+    let mut headers = HashMap::new();
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    return Ok(Request {
+        method: method.to_string(),
+        path: decode_component(path),
+        query,
+        headers,
+    });
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    return stream.flush();
+}
+
+fn respond_json(stream: &mut TcpStream, value: &serde_json::Value) -> io::Result<()> {
+    return respond(
+        stream,
+        "200 OK",
+        "application/json",
+        value.to_string().as_bytes(),
+    );
+}
+
+/// streams a video file, honouring a single `Range` header so the player can seek
+fn stream_file(
+    stream: &mut TcpStream,
+    path: &Path,
+    headers: &HashMap<String, String>,
+) -> io::Result<()> {
+    let Ok(mut file) = File::open(path) else {
+        return respond(stream, "404 Not Found", "text/plain", b"video not found");
+    };
+    let len = file.metadata()?.len();
+    let content_type = content_type(path);
+    let (status, start, end) = match headers.get("range") {
+        None => ("200 OK", 0, len.saturating_sub(1)),
+        Some(range) => match parse_range(range, len) {
+            Some((start, end)) => ("206 Partial Content", start, end),
+            None => {
+                write!(
+                    stream,
+                    "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    len
+                )?;
+                return stream.flush();
+            }
+        },
+    };
+    let body_len = if len == 0 { 0 } else { end - start + 1 };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n",
+        status, content_type, body_len
+    )?;
+    if status.starts_with("206") {
+        write!(stream, "Content-Range: bytes {}-{}/{}\r\n", start, end, len)?;
+    }
+    write!(stream, "\r\n")?;
+    file.seek(SeekFrom::Start(start))?;
+    io::copy(&mut file.take(body_len), stream)?;
+    return stream.flush();
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        "mov" => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+/// parses `bytes=start-end`, `bytes=start-` and `bytes=-suffix` into an inclusive range
+pub fn parse_range(header: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    // multiple ranges are not supported, only the first one is served
+    let spec = spec.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    if len == 0 {
+        return None;
+    }
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return None,
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?.min(len);
+            if suffix == 0 {
+                return None;
+            }
+            (len - suffix, len - 1)
+        }
+        (start, "") => (start.parse::<u64>().ok()?, len - 1),
+        (start, end) => (
+            start.parse::<u64>().ok()?,
+            end.parse::<u64>().ok()?.min(len - 1),
+        ),
+    };
+    if start > end || start >= len {
+        return None;
+    }
+    return Some((start, end));
+}
+
+/// decodes a percent-encoded url component, `+` is treated as a space
+pub fn decode_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    return String::from_utf8_lossy(&decoded).to_string();
+}
@@ -0,0 +1,100 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::eval;
+
+/// shorter words are left alone, there are too many real words one edit apart
+const MIN_LENGTH: usize = 5;
+/// words up to this long are corrected by one edit at most, longer ones by two
+const ONE_EDIT_LENGTH: usize = 8;
+
+/// a SymSpell dictionary: every word is stored under the strings left after deleting up to
+/// `max_distance` of its characters, so a lookup only has to generate the term's own deletes
+/// instead of every possible edit
+pub struct SymSpell {
+    max_distance: usize,
+    words: Vec<String>,
+    /// delete variant -> positions in `words`
+    deletes: HashMap<String, Vec<usize>>,
+}
+
+impl SymSpell {
+    pub fn new(max_distance: usize) -> Self {
+        return Self {
+            max_distance,
+            words: Vec::new(),
+            deletes: HashMap::new(),
+        };
+    }
+    pub fn add(&mut self, word: &str) {
+        if self.contains(word) {
+            return;
+        }
+        self.words.push(word.to_string());
+        for variant in deletes(word, self.max_distance) {
+            self.deletes
+                .entry(variant)
+                .or_default()
+                .push(self.words.len() - 1);
+        }
+    }
+    pub fn contains(&self, word: &str) -> bool {
+        return self
+            .deletes
+            .get(word)
+            .is_some_and(|words| words.iter().any(|&i| self.words[i] == word));
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.words.is_empty();
+    }
+    /// the words at most `max_distance` edits from `term`, nearest first
+    pub fn lookup(&self, term: &str, max_distance: usize) -> Vec<(&str, usize)> {
+        let max_distance = max_distance.min(self.max_distance);
+        let term_chars: Vec<char> = term.chars().collect();
+        let mut found: HashSet<usize> = HashSet::new();
+        for variant in deletes(term, max_distance) {
+            found.extend(self.deletes.get(&variant).into_iter().flatten());
+        }
+        let mut matches: Vec<(&str, usize)> = found
+            .into_iter()
+            .map(|i| {
+                let word: Vec<char> = self.words[i].chars().collect();
+                (
+                    self.words[i].as_str(),
+                    eval::edit_distance(&term_chars, &word),
+                )
+            })
+            .filter(|(_, distance)| *distance <= max_distance)
+            .collect();
+        matches.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(b.0)));
+        return matches;
+    }
+}
+
+/// the word itself and every string left after deleting up to `max_distance` characters
+fn deletes(word: &str, max_distance: usize) -> HashSet<String> {
+    let mut variants = HashSet::from([word.to_string()]);
+    let mut last = vec![word.to_string()];
+    for _ in 0..max_distance {
+        let mut next = Vec::new();
+        for variant in &last {
+            let chars: Vec<char> = variant.chars().collect();
+            for i in 0..chars.len() {
+                let deleted: String = chars[..i].iter().chain(&chars[i + 1..]).collect();
+                if variants.insert(deleted.clone()) {
+                    next.push(deleted);
+                }
+            }
+        }
+        last = next;
+    }
+    return variants;
+}
+
+/// how many edits a misread word may be from its correction, 0 for words too short to correct
+pub fn max_distance(word: &str) -> usize {
+    match word.chars().count() {
+        n if n < MIN_LENGTH => 0,
+        n if n <= ONE_EDIT_LENGTH => 1,
+        _ => 2,
+    }
+}
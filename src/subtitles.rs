@@ -0,0 +1,267 @@
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// sidecar subtitle formats that can be parsed
+pub const SIDECAR_EXTENSIONS: [&str; 3] = ["srt", "vtt", "ass"];
+/// subtitle codecs that are images and can't be converted to text
+const BITMAP_CODECS: [&str; 4] = ["hdmv_pgs_subtitle", "dvd_subtitle", "dvb_subtitle", "xsub"];
+
+/// a timed piece of text, times are in milliseconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: u64,
+    pub end: u64,
+    pub text: String,
+}
+
+/// re-cuts cues so no line is longer than `max_line_len` characters (0 disables it),
+/// or into one cue per word if `word_level` is set.
+/// word times are interpolated across the cue by their length
+pub fn split_cues(cues: &[Cue], max_line_len: usize, word_level: bool) -> Vec<Cue> {
+    let mut result = Vec::new();
+    for cue in cues {
+        let words = timed_words(cue);
+        let mut line: Vec<&Cue> = Vec::new();
+        let mut line_len = 0;
+        for word in &words {
+            let fits =
+                max_line_len == 0 || line_len + 1 + word.text.chars().count() <= max_line_len;
+            if !line.is_empty() && (word_level || !fits) {
+                result.push(join_words(&line));
+                line.clear();
+                line_len = 0;
+            }
+            line_len += word.text.chars().count() + usize::from(!line.is_empty());
+            line.push(word);
+        }
+        if !line.is_empty() {
+            result.push(join_words(&line));
+        }
+    }
+    return result;
+}
+
+fn timed_words(cue: &Cue) -> Vec<Cue> {
+    let words: Vec<&str> = cue.text.split_whitespace().collect();
+    let total: usize = words.iter().map(|w| w.chars().count()).sum();
+    let duration = cue.end.saturating_sub(cue.start);
+    let mut elapsed = 0;
+    return words
+        .iter()
+        .map(|word| {
+            let start = cue.start + duration * elapsed as u64 / total.max(1) as u64;
+            elapsed += word.chars().count();
+            let end = cue.start + duration * elapsed as u64 / total.max(1) as u64;
+            Cue {
+                start,
+                end,
+                text: word.to_string(),
+            }
+        })
+        .collect();
+}
+
+fn join_words(words: &[&Cue]) -> Cue {
+    return Cue {
+        start: words[0].start,
+        end: words[words.len() - 1].end,
+        text: words
+            .iter()
+            .map(|w| w.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+}
+
+fn format_time(ms: u64, separator: char) -> String {
+    return format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        (ms % 3_600_000) / 60_000,
+        (ms % 60_000) / 1000,
+        separator,
+        ms % 1000
+    );
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_time(cue.start, ','),
+            format_time(cue.end, ','),
+            cue.text.trim()
+        ));
+    }
+    return srt;
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        vtt.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_time(cue.start, '.'),
+            format_time(cue.end, '.'),
+            cue.text.trim()
+        ));
+    }
+    return vtt;
+}
+
+/// writes `<name>.srt` and `<name>.vtt` into `dir`
+pub fn save(cues: &[Cue], dir: &Path, name: &str) -> std::io::Result<()> {
+    fs::write(dir.join(format!("{}.srt", name)), to_srt(cues))?;
+    fs::write(dir.join(format!("{}.vtt", name)), to_vtt(cues))?;
+    Ok(())
+}
+
+pub fn is_text_codec(codec: &str) -> bool {
+    return !BITMAP_CODECS.contains(&codec);
+}
+
+/// finds subtitle files next to a video, `talk.srt` and `talk.en.vtt` both belong to `talk.mp4`
+pub fn find_sidecars(video_path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (video_path.parent(), video_path.file_stem()) else {
+        return Vec::new();
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let stem = stem.to_string_lossy();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut sidecars: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let extension = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            SIDECAR_EXTENSIONS.contains(&extension.as_str())
+                && name
+                    .strip_prefix(stem.as_ref())
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+        .collect();
+    sidecars.sort();
+    return sidecars;
+}
+
+/// parses an .srt, .vtt or .ass file based on its extension
+pub fn parse_file(path: &Path) -> std::io::Result<Vec<Cue>> {
+    let contents = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "ass" | "ssa" => Ok(parse_ass(&contents)),
+        _ => Ok(parse_timed_text(&contents)),
+    }
+}
+
+/// parses SRT and WebVTT, both are blocks of a `start --> end` line followed by text lines
+pub fn parse_timed_text(contents: &str) -> Vec<Cue> {
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let contents = contents
+        .trim_start_matches('\u{feff}')
+        .replace("\r\n", "\n");
+    let mut cues = Vec::new();
+    for block in contents.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+        let Some(timing) = lines.next() else {
+            continue;
+        };
+        let Some((start, end)) = timing.split_once("-->") else {
+            continue;
+        };
+        // vtt allows cue settings after the end time
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let (Some(start), Some(end)) = (parse_time(start.trim()), parse_time(end)) else {
+            continue;
+        };
+        let text = lines
+            .map(|line| tags.replace_all(line.trim(), "").to_string())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() {
+            cues.push(Cue { start, end, text });
+        }
+    }
+    return cues;
+}
+
+/// parses the `Dialogue:` lines of an Advanced SubStation Alpha file
+pub fn parse_ass(contents: &str) -> Vec<Cue> {
+    let overrides = Regex::new(r"\{[^}]*\}").unwrap();
+    let mut format: Vec<String> = [
+        "layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text",
+    ]
+    .iter()
+    .map(|f| f.to_string())
+    .collect();
+    let mut cues = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some(fields) = line.strip_prefix("Format:") {
+            format = fields.split(',').map(|f| f.trim().to_lowercase()).collect();
+            continue;
+        }
+        let Some(fields) = line.strip_prefix("Dialogue:") else {
+            continue;
+        };
+        // the text is always the last field and may contain commas
+        let fields: Vec<&str> = fields.splitn(format.len(), ',').collect();
+        let field = |name: &str| {
+            format
+                .iter()
+                .position(|f| f == name)
+                .and_then(|i| fields.get(i))
+                .map(|f| f.trim())
+        };
+        let (Some(start), Some(end), Some(text)) = (
+            field("start").and_then(parse_time),
+            field("end").and_then(parse_time),
+            field("text"),
+        ) else {
+            continue;
+        };
+        let text = overrides
+            .replace_all(text, "")
+            .replace("\\N", " ")
+            .replace("\\n", " ")
+            .replace("\\h", " ");
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            cues.push(Cue { start, end, text });
+        }
+    }
+    return cues;
+}
+
+/// parses `HH:MM:SS,mmm`, `HH:MM:SS.mmm`, `MM:SS.mmm` and `H:MM:SS.cc` into milliseconds
+fn parse_time(time: &str) -> Option<u64> {
+    let (clock, fraction) = match time.rsplit_once([',', '.']) {
+        Some((clock, fraction)) => (clock, fraction),
+        None => (time, ""),
+    };
+    let mut seconds = 0;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.trim().parse::<u64>().ok()?;
+    }
+    let mut millis = 0;
+    for (i, digit) in fraction.chars().chain("000".chars()).take(3).enumerate() {
+        millis += digit.to_digit(10)? as u64 * 10u64.pow(2 - i as u32);
+    }
+    return Some(seconds * 1000 + millis);
+}
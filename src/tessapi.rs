@@ -0,0 +1,129 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+
+use leptess::capi;
+
+/// the tesseract C api, for what the bindings don't expose:
+/// variables set at init and frames handed over as raw pixels
+pub struct TessApi {
+    handle: *mut capi::TessBaseAPI,
+}
+
+// the handle is only ever used by the engine owning it
+unsafe impl Send for TessApi {}
+
+fn c_string(s: &str) -> Result<CString, String> {
+    return CString::new(s).map_err(|_| format!("'{}' contains a nul byte", s));
+}
+
+impl TessApi {
+    /// loads `lang` with `variables` already set, so they also apply to the language
+    /// loading itself, which `user_words_file` and `user_patterns_file` need
+    pub fn new(
+        tessdata_dir: Option<&str>,
+        lang: &str,
+        oem: u32,
+        variables: &[(&str, String)],
+    ) -> Result<Self, String> {
+        let tessdata_dir = tessdata_dir.map(c_string).transpose()?;
+        let lang = c_string(lang)?;
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        for (name, value) in variables {
+            names.push(c_string(name)?);
+            values.push(c_string(value)?);
+        }
+        // tesseract takes `char **` but doesn't write through them
+        let mut name_ptrs: Vec<*mut c_char> = names.iter().map(|s| s.as_ptr() as _).collect();
+        let mut value_ptrs: Vec<*mut c_char> = values.iter().map(|s| s.as_ptr() as _).collect();
+        let handle = unsafe { capi::TessBaseAPICreate() };
+        let status = unsafe {
+            capi::TessBaseAPIInit4(
+                handle,
+                tessdata_dir
+                    .as_ref()
+                    .map_or(ptr::null(), |dir| dir.as_ptr()),
+                lang.as_ptr(),
+                oem as capi::TessOcrEngineMode,
+                ptr::null_mut(),
+                0,
+                name_ptrs.as_mut_ptr(),
+                value_ptrs.as_mut_ptr(),
+                name_ptrs.len() as _,
+                0,
+            )
+        };
+        let api = Self { handle };
+        if status != 0 {
+            return Err(format!(
+                "failed to initialize tesseract with language '{}'",
+                lang.to_string_lossy()
+            ));
+        }
+        return Ok(api);
+    }
+    /// reads the image with leptonica
+    pub fn set_image_file(&mut self, path: &Path) -> Result<(), String> {
+        let c_path = c_string(&path.to_string_lossy())?;
+        let mut pix = unsafe { capi::pixRead(c_path.as_ptr()) };
+        if pix.is_null() {
+            return Err(format!("failed to read image {}", path.display()));
+        }
+        unsafe {
+            // tesseract keeps its own reference to the image
+            capi::TessBaseAPISetImage2(self.handle, pix);
+            capi::pixDestroy(&mut pix);
+        }
+        return Ok(());
+    }
+    /// copies the pixels of a frame in memory, rows packed without padding
+    pub fn set_frame(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        bytes_per_pixel: u32,
+    ) -> Result<(), String> {
+        let stride = width * bytes_per_pixel;
+        if data.len() != (stride * height) as usize {
+            return Err(format!(
+                "a {}x{} frame of {} bytes per pixel can't hold {} bytes",
+                width,
+                height,
+                bytes_per_pixel,
+                data.len()
+            ));
+        }
+        unsafe {
+            capi::TessBaseAPISetImage(
+                self.handle,
+                data.as_ptr(),
+                width as _,
+                height as _,
+                bytes_per_pixel as _,
+                stride as _,
+            );
+        }
+        return Ok(());
+    }
+    /// recognizes the image and returns the words as TSV
+    pub fn get_tsv_text(&mut self) -> Result<String, String> {
+        let text = unsafe { capi::TessBaseAPIGetTsvText(self.handle, 0) };
+        if text.is_null() {
+            return Err("tesseract failed to recognize the image".to_string());
+        }
+        let tsv = unsafe { CStr::from_ptr(text) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { capi::TessDeleteText(text) };
+        return Ok(tsv);
+    }
+}
+
+impl Drop for TessApi {
+    fn drop(&mut self) {
+        unsafe { capi::TessBaseAPIDelete(self.handle) };
+    }
+}
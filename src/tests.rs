@@ -1,13 +1,37 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
-    use std::path::Path;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
 
+    use image::{DynamicImage, GrayImage, Luma};
+
+    use crate::analyzer::{self, Analyzer};
+    use crate::asr::{self, AsrEngine};
+    use crate::captions;
+    use crate::config::Options;
+    use crate::dedup;
+    use crate::draw;
+    use crate::ensemble;
+    use crate::eval;
     use crate::gec;
-    use crate::indexer::Indexer;
+    use crate::glossary::Glossary;
+    use crate::indexer::{Correction, GlossaryTerm, Indexer, OverlayMode, Source};
+    use crate::mask::{self, Area, Mask};
     use crate::ocr;
+    use crate::preprocessing::{self, Pipeline, Step};
+    use crate::query::Query;
+    use crate::refine;
+    use crate::serve;
+    use crate::spell::{self, SymSpell};
+    use crate::subtitles::{self, Cue};
+    use crate::textdetect;
     use crate::trie::Trie;
     use crate::vidsplicer;
+    use crate::vidsplicer::ffmpeg_utils::Sampling;
+    use crate::vidsplicer::frames_iterator::{self, PixelFormat};
 
     #[test]
     fn trie() {
@@ -50,6 +74,51 @@ mod tests {
         assert_eq!(tokens.contains("it's"), false);
     }
 
+    #[test]
+    fn code_tokenizer() {
+        let mut indexer = Indexer::new();
+        indexer.analyzer = Analyzer::Code;
+        let tokens =
+            indexer.tokenize("let dims = get_video_dims(path)?; HashMap::new() -> parseFrameRate");
+        for token in [
+            "get_video_dims",
+            "video",
+            "dims",
+            "hashmap::new",
+            "hash",
+            "map",
+            "parseframerate",
+            "frame",
+            "rate",
+            "::",
+            "->",
+        ] {
+            assert!(tokens.contains(token), "missing {}", token);
+        }
+        assert_eq!(analyzer::subwords("HTTPServer"), ["HTTP", "Server"]);
+        assert_eq!(analyzer::subwords("utf8"), Vec::<&str>::new());
+        // plain text mode is unchanged
+        assert!(!Indexer::new()
+            .tokenize("HashMap::new")
+            .contains("hashmap::new"));
+
+        indexer.update("let map = HashMap::new();", 10);
+        indexer.update("fn parseFrameRate(fps: &str) -> u64", 20);
+        indexer.update("use std::collections::HashMap;", 30);
+        assert_eq!(indexer.search("HashMap::new"), [10]);
+        assert_eq!(indexer.search("frame rate"), [20]);
+        assert_eq!(indexer.search("->"), [20]);
+
+        let tsv = "1\t1\t0\t0\t0\t0\t0\t0\t640\t480\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t10\t10\t20\t10\t90\tfn\n\
+                   5\t1\t1\t1\t1\t2\t40\t10\t40\t10\t90\tmain()\n\
+                   5\t1\t1\t1\t2\t1\t50\t30\t30\t10\t90\tlet\n\
+                   5\t1\t1\t1\t3\t1\t10\t50\t10\t10\t90\t}\n";
+        let mut result = ocr::OcrResult::from_tsv(tsv);
+        result.indent_lines();
+        assert_eq!(result.text, "fn main()\n    let\n}");
+    }
+
     #[test]
     fn gec() {
         assert_eq!(
@@ -61,32 +130,259 @@ mod tests {
             String::from("I was going to the mall today.")
         );
     }
+    fn recognize(backend: ocr::Backend, settings: &ocr::OcrSettings) -> HashSet<String> {
+        let mut engine = ocr::make_engine(backend, settings).unwrap();
+        let res = engine.recognize(Path::new("data/test.png")).unwrap();
+        return res
+            .text
+            .to_lowercase()
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+    }
+    fn expected_text() -> HashSet<String> {
+        return r#"Pure Text"#.to_lowercase().split_whitespace().map(String::from).collect();
+    }
     #[test]
     fn lep_ocr() {
-        let res = ocr::ocr("data/test.png", &mut ocr::get_api()).to_lowercase();
-        let res = res.split_whitespace().collect::<HashSet<&str>>();
-
-        let expected = r#"Pure Text"#.to_lowercase();
-        let expected = expected.split_whitespace().collect::<HashSet<&str>>();
-        assert_eq!(expected, res);
+        let res = recognize(ocr::Backend::LepTess, &ocr::OcrSettings::default());
+        assert_eq!(expected_text(), res);
     }
     #[test]
     fn tess_ocr() {
-        let res = ocr::tess_ocr("data/test.png", ocr::get_tess_api()).to_lowercase();
-        let res = res.split_whitespace().collect::<HashSet<&str>>();
-
-        let expected = r#"Pure Text"#.to_lowercase();
-        let expected = expected.split_whitespace().collect::<HashSet<&str>>();
-        assert_eq!(expected, res);
+        let settings = ocr::OcrSettings {
+            psm: 12,
+            dpi: 0,
+            tessdata_dir: None,
+            ..Default::default()
+        };
+        let res = recognize(ocr::Backend::Tesseract, &settings);
+        assert_eq!(expected_text(), res);
+    }
+    #[test]
+    fn cli_ocr() {
+        let settings = ocr::OcrSettings {
+            psm: 12,
+            tessdata_dir: Some("models/traineddata/tessdata_fast".to_string()),
+            ..Default::default()
+        };
+        let res = recognize(ocr::Backend::TesseractCli, &settings);
+        assert_eq!(expected_text(), res);
+    }
+    #[test]
+    fn cli_tessdata_dir() {
+        let engine = ocr::TesseractCliEngine::new(&ocr::OcrSettings {
+            tessdata_dir: Some("models/traineddata/tessdata_fast".to_string()),
+            ..Default::default()
+        });
+        let command = engine.command(Path::new("frame.png"));
+        let args: Vec<&str> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert_eq!(args[..2], ["frame.png", "stdout"]);
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["--tessdata-dir", "models/traineddata/tessdata_fast"]));
+        assert_eq!(args.last(), Some(&"tsv"));
+        // the location is passed to the command, not set for the whole process
+        assert!(command.get_envs().next().is_none());
+    }
+    /// share of the expected words that were recognized
+    fn word_recall(expected: &HashSet<String>, recognized: &HashSet<String>) -> f32 {
+        return expected.intersection(recognized).count() as f32 / expected.len().max(1) as f32;
+    }
+    /// how far the mean recall with preprocessing may fall below the one without
+    const RECALL_TOLERANCE: f32 = 0.05;
+    #[test]
+    fn preprocessing_recall() {
+        let pipelines: [(&str, Pipeline); 2] = [
+            ("off", "none".parse().unwrap()),
+            (
+                "on",
+                "gray,upscale,otsu,invert,denoise,deskew".parse().unwrap(),
+            ),
+        ];
+        let mut frames: Vec<_> = std::fs::read_dir("data/ocr_eval")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+            .collect();
+        frames.sort();
+        let words = |text: &str| -> HashSet<String> {
+            text.to_lowercase()
+                .split_whitespace()
+                .map(String::from)
+                .collect()
+        };
+        let mut engine =
+            ocr::make_engine(ocr::Backend::LepTess, &ocr::OcrSettings::default()).unwrap();
+        // one row per frame and a mean row, one column per pipeline
+        let mut report = String::from("frame");
+        for (name, _) in &pipelines {
+            report += &format!("\t{}", name);
+        }
+        let mut means = vec![0.0; pipelines.len()];
+        for frame in &frames {
+            let expected = words(&std::fs::read_to_string(frame.with_extension("txt")).unwrap());
+            report += &format!("\n{}", frame.file_stem().unwrap().to_string_lossy());
+            for ((_, pipeline), mean) in pipelines.iter().zip(&mut means) {
+                let image = image::open(frame).unwrap();
+                let result = engine.recognize_image(&pipeline.apply(image)).unwrap();
+                let recall = word_recall(&expected, &words(&result.text));
+                *mean += recall / frames.len() as f32;
+                report += &format!("\t{:.3}", recall);
+            }
+        }
+        report += "\nmean";
+        for mean in &means {
+            report += &format!("\t{:.3}", mean);
+        }
+        // both pipelines read the same frames in the same run, only their difference matters
+        let (off, on) = (means[0], means[1]);
+        assert!(
+            on >= off - RECALL_TOLERANCE,
+            "mean recall fell from {:.3} to {:.3} with preprocessing:\n{}",
+            off,
+            on,
+            report
+        );
+    }
+    #[test]
+    fn preprocessing_steps() {
+        let pipeline: Pipeline = "gray, upscale:720, adaptive:10, deskew".parse().unwrap();
+        assert_eq!(
+            pipeline.steps,
+            [
+                Step::Grayscale,
+                Step::Upscale(720),
+                Step::Adaptive(10),
+                Step::Deskew
+            ]
+        );
+        assert!("none".parse::<Pipeline>().unwrap().is_empty());
+        assert!("gray,sharpen".parse::<Pipeline>().is_err());
+        assert!("upscale:big".parse::<Pipeline>().is_err());
+        // light text on a dark slide
+        let slide = GrayImage::from_fn(200, 100, |x, y| {
+            match (40..160).contains(&x) && (45..55).contains(&y) {
+                true => Luma([230]),
+                false => Luma([20]),
+            }
+        });
+        assert!(preprocessing::is_dark(&slide));
+        let threshold = preprocessing::otsu_threshold(&slide);
+        assert!((20..230).contains(&threshold));
+        let inverted = Pipeline {
+            steps: vec![Step::Otsu, Step::Invert],
+        }
+        .apply(DynamicImage::ImageLuma8(slide))
+        .to_luma8();
+        assert!(!preprocessing::is_dark(&inverted));
+        assert_eq!(inverted.get_pixel(100, 50)[0], 0);
+        assert_eq!(inverted.get_pixel(5, 5)[0], 255);
+        // small frames are upscaled, large ones are left alone
+        let small = DynamicImage::ImageLuma8(GrayImage::new(320, 180));
+        let upscaled = Pipeline {
+            steps: vec![Step::Upscale(720)],
+        }
+        .apply(small);
+        assert_eq!((upscaled.width(), upscaled.height()), (1280, 720));
+        // dark lines tilted by 3 degrees are straightened
+        let tilt = 3f32.to_radians().tan();
+        let skewed = GrayImage::from_fn(300, 200, |x, y| {
+            let y = y as f32 - x as f32 * tilt;
+            match [40.0, 90.0, 140.0]
+                .iter()
+                .any(|line| (y - line).abs() < 2.0)
+            {
+                true => Luma([0]),
+                false => Luma([255]),
+            }
+        });
+        assert!((preprocessing::skew_angle(&skewed) - 3.0).abs() <= 0.25);
+        let straight = Pipeline {
+            steps: vec![Step::Deskew],
+        }
+        .apply(DynamicImage::ImageLuma8(skewed))
+        .to_luma8();
+        assert!(preprocessing::skew_angle(&straight).abs() <= 0.25);
     }
     #[test]
-    fn rt_ocr() {
-        let res = ocr::rt_ocr("data/test.png").to_lowercase();
-        let res = res.split_whitespace().collect::<HashSet<&str>>();
+    fn text_detection() {
+        // a "line of text": glyph-sized strokes on a plain background, in a 1280x720 frame
+        let text = |x: u32, y: u32| (200..600).contains(&x) && (300..330).contains(&y) && x % 9 < 3;
+        let slide = GrayImage::from_fn(1280, 720, |x, y| match text(x, y) {
+            true => Luma([20]),
+            false => Luma([235]),
+        });
+        let regions = textdetect::text_regions(&DynamicImage::ImageLuma8(slide));
+        assert_eq!(regions.len(), 1);
+        let region = regions[0];
+        assert!(region.left <= 200 && region.left + region.width >= 600);
+        assert!(region.top <= 300 && region.top + region.height >= 330);
+        assert!(region.height < 80);
+
+        // smooth shading, like a face or a sky, has no sharp edges
+        let shading = GrayImage::from_fn(1280, 720, |x, y| Luma([((x + y) / 8 % 256) as u8]));
+        assert!(textdetect::text_regions(&DynamicImage::ImageLuma8(shading)).is_empty());
+        let blank = GrayImage::from_pixel(640, 360, Luma([128]));
+        assert!(textdetect::text_regions(&DynamicImage::ImageLuma8(blank)).is_empty());
 
-        let expected = r#"Pure Text"#.to_lowercase();
-        let expected = expected.split_whitespace().collect::<HashSet<&str>>();
-        assert_eq!(expected, res);
+        // words of crops are moved back into the frame
+        let tsv = "5\t1\t1\t1\t1\t1\t4\t2\t30\t10\t90\tfn\n";
+        let merged = ocr::OcrResult::merge(
+            vec![
+                (ocr::OcrResult::from_tsv(tsv), 100, 50),
+                (ocr::OcrResult::from_tsv(tsv), 100, 400),
+            ],
+            1280,
+            720,
+        );
+        assert_eq!(merged.text, "fn\nfn");
+        assert_eq!(
+            (merged.words[1].bbox.left, merged.words[1].bbox.top),
+            (104, 402)
+        );
+    }
+    #[test]
+    fn scaled_boxes() {
+        let tsv = "1\t1\t0\t0\t0\t0\t0\t0\t1280\t720\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t100\t40\t200\t60\t96\tPure\n";
+        let mut result = ocr::OcrResult::from_tsv(tsv);
+        result.scale_to(640, 360);
+        assert_eq!((result.width, result.height), (640, 360));
+        assert_eq!(
+            result.words[0].bbox,
+            ocr::BoundingBox {
+                left: 50,
+                top: 20,
+                width: 100,
+                height: 30
+            }
+        );
+    }
+    #[test]
+    fn tsv_parsing() {
+        let tsv = "1\t1\t0\t0\t0\t0\t0\t0\t640\t480\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t10\t10\t50\t20\t96.5\tPure\n\
+                   5\t1\t1\t1\t1\t2\t70\t10\t50\t20\t91\tText\n\
+                   5\t1\t1\t1\t2\t1\t10\t40\t50\t20\t88\tbelow\n\
+                   5\t1\t1\t1\t2\t2\t70\t40\t50\t20\t-1\t \n";
+        let result = ocr::OcrResult::from_tsv(tsv);
+        assert_eq!(result.text, "Pure Text\nbelow");
+        assert_eq!(result.words.len(), 3);
+        assert_eq!(result.words[0].confidence, 96.5);
+        assert_eq!((result.width, result.height), (640, 480));
+        assert_eq!(
+            result.words[2].bbox,
+            ocr::BoundingBox {
+                left: 10,
+                top: 40,
+                width: 50,
+                height: 20
+            }
+        );
     }
     #[test]
     fn metadata() {
@@ -102,6 +398,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn languages() {
+        let args: Vec<String> = ["talk.mp4", "--ocr-lang", "eng+ara+deu", "--asr-lang=auto"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (options, _) = Options::parse(&args).unwrap();
+        assert_eq!(options.ocr.lang, "eng+ara+deu");
+        assert_eq!(options.asr.language, asr::AUTO_LANGUAGE);
+        assert!(options.asr.is_english_only());
+        assert_eq!(asr::most_likely(&[0.1, 0.7, f32::NAN, 0.2]), Some(1));
+        assert_eq!(asr::most_likely(&[]), None);
+
+        let mut indexer = Indexer::new();
+        indexer.metadata.language = Some("de".to_string());
+        indexer.update("Straße", 3);
+        let json = indexer.serialize().to_string();
+        let loaded: Indexer = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.metadata.language.as_deref(), Some("de"));
+        assert_eq!(loaded.search("straße"), [3]);
+    }
+
     #[test]
     fn get_timestamp() {
         let filename = "000513.jpg";
@@ -111,4 +429,1054 @@ mod tests {
         frame_number /= fps;
         assert_eq!(frame_number, 513);
     }
+
+    #[test]
+    fn options() {
+        let args: Vec<String> = ["serve", "dump/a", "--port", "9000", "dump/b"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (options, positional) = Options::parse(&args).unwrap();
+        assert_eq!(options.port, 9000);
+        assert_eq!(positional, ["serve", "dump/a", "dump/b"]);
+        assert!(Options::parse(&["--port".to_string()]).is_err());
+        assert!(Options::parse(&["--colour=red".to_string()]).is_err());
+        let mut options = Options::default();
+        options
+            .apply_file("ocr_backend = \"tesseract\"\nocr_psm = 6\ndebug_boxes = true")
+            .unwrap();
+        assert_eq!(options.ocr_backend, ocr::Backend::Tesseract);
+        assert_eq!(options.ocr.psm, 6);
+        assert!(options.debug_boxes);
+    }
+
+    #[test]
+    fn sampling() {
+        assert_eq!("keyframes".parse(), Ok(Sampling::Keyframes));
+        assert_eq!("interval:2.5".parse(), Ok(Sampling::Interval(2.5)));
+        assert_eq!("scene".parse(), Ok(Sampling::Scene(0.3)));
+        let hybrid: Sampling = "hybrid:0.2:8".parse().unwrap();
+        assert_eq!(
+            hybrid,
+            Sampling::Hybrid {
+                scene: 0.2,
+                max_gap: 8.0
+            }
+        );
+        assert!("interval:0".parse::<Sampling>().is_err());
+        assert!("every:5".parse::<Sampling>().is_err());
+
+        assert_eq!(Sampling::Keyframes.input_args(), ["-skip_frame", "nokey"]);
+        assert_eq!(Sampling::Interval(5.0).output_args(&[]), ["-vf", "fps=1/5"]);
+        assert_eq!(
+            hybrid.output_args(&[]),
+            [
+                "-vf",
+                "select='eq(n,0)+gt(scene,0.2)+gte(t-prev_selected_t,8)'",
+                "-fps_mode",
+                "passthrough"
+            ]
+        );
+        assert!(hybrid.input_args().is_empty());
+    }
+
+    #[test]
+    fn range_header() {
+        assert_eq!(serve::parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(serve::parse_range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(serve::parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(serve::parse_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(serve::parse_range("bytes=1000-", 1000), None);
+        assert_eq!(serve::parse_range("bytes=20-10", 1000), None);
+        assert_eq!(serve::parse_range("items=0-1", 1000), None);
+        assert_eq!(
+            serve::decode_component("hash%3A%3Amap+new"),
+            "hash::map new"
+        );
+    }
+
+    #[test]
+    fn serve_search() {
+        let mut indexer = Indexer::new();
+        indexer.update("Pure Text", 42);
+        let library = serve::Library {
+            videos: vec![serve::Video {
+                name: "demo.mp4".to_string(),
+                indexer,
+            }],
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve::run(listener, Arc::new(library)));
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /api/search?q=pure%20text HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let hits: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(hits[0]["timestamp"], 42);
+        assert_eq!(hits[0]["time"], "00:00:42");
+    }
+
+    #[test]
+    fn snippets() {
+        let mut indexer = Indexer::new();
+        indexer.record(
+            Source::Asr,
+            3,
+            7,
+            "so today we will talk\nabout the Rust borrow checker",
+        );
+        indexer.record(
+            Source::Ocr,
+            9,
+            9,
+            "one two three four five six seven eight nine ten Rust eleven twelve",
+        );
+        let hits = indexer.search_hits(&Query::parse("rust").unwrap());
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].timestamp, 3);
+        let snippet = &hits[0].snippets[0];
+        assert_eq!(snippet.source, Source::Asr);
+        assert_eq!(
+            snippet.highlight("[", "]"),
+            "so today we will talk about the [Rust] borrow checker"
+        );
+        assert_eq!(
+            hits[1].snippets[0].highlight("[", "]"),
+            "… three four five six seven eight nine ten [Rust] eleven twelve"
+        );
+
+        // code terms are found in the snippet the way the index split them
+        let mut code = Indexer::new();
+        code.analyzer = Analyzer::Code;
+        code.record(
+            Source::Ocr,
+            0,
+            9,
+            "let dims = cfg.video_dims(path)?; x -> y",
+        );
+        let hits = code.search_hits(&Query::parse("cfg.video_dims").unwrap());
+        assert_eq!(
+            hits[0].snippets[0].highlight("[", "]"),
+            "let [dims] = [cfg.video_dims](path)?; x -> y"
+        );
+        let hits = code.search_hits(&Query::parse("video_dims").unwrap());
+        assert_eq!(
+            hits[0].snippets[0].highlight("[", "]"),
+            "let [dims] = cfg.[video_dims](path)?; x -> y"
+        );
+        let hits = code.search_hits(&Query::parse("->").unwrap());
+        assert_eq!(
+            hits[0].snippets[0].highlight("[", "]"),
+            "let dims = cfg.video_dims(path)?; x [->] y"
+        );
+    }
+
+    #[test]
+    fn reverse_lookup() {
+        let mut indexer = Indexer::new();
+        indexer.record(Source::Ocr, 10, 10, "Title slide");
+        indexer.record(Source::Ocr, 20, 20, "Agenda");
+        indexer.record(Source::Asr, 12, 18, "welcome everyone");
+        indexer.record(Source::Asr, 18, 25, "let's look at the agenda");
+
+        let moment = indexer.at(14);
+        assert_eq!(moment.frame.unwrap().text, "Title slide");
+        assert_eq!(moment.segments.len(), 1);
+        assert_eq!(moment.segments[0].text, "welcome everyone");
+
+        let moment = indexer.at(18);
+        assert_eq!(moment.frame.unwrap().text, "Agenda");
+        assert_eq!(moment.segments.len(), 2);
+
+        assert!(indexer.at(40).segments.is_empty());
+        assert_eq!(indexer.at(40).frame.unwrap().start, 20);
+        // a deduplicated frame stays on screen until its last duplicate
+        indexer.record(Source::Ocr, 50, 80, "Summary");
+        assert_eq!(indexer.at(78).frame.unwrap().text, "Summary");
+        assert_eq!(crate::parse_timestamp("00:12:30"), Some(750));
+        assert_eq!(crate::parse_timestamp("2:05"), Some(125));
+        assert_eq!(crate::parse_timestamp("1:2:3:4"), None);
+    }
+
+    #[test]
+    fn frame_dedup() {
+        let slide = |offset: u32| {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(160, 90, |x, _| {
+                Luma([((x + offset) % 160 * 255 / 160) as u8])
+            }))
+        };
+        let title = dedup::dhash(&slide(0));
+        let same = dedup::dhash(&slide(0).brighten(10));
+        let other = dedup::dhash(&slide(80));
+        assert_eq!(dedup::similarity(title, same), 1.0);
+        assert!(dedup::similarity(title, other) < 0.9);
+
+        let frames = || {
+            [(title, 0), (same, 4), (same, 8), (other, 12), (title, 16)]
+                .iter()
+                .map(|(hash, timestamp)| {
+                    (
+                        *hash,
+                        PathBuf::from(format!("{}.jpg", timestamp)),
+                        *timestamp,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let (shots, skipped) = dedup::fold(frames(), 0.95);
+        assert_eq!(skipped, 2);
+        let ranges: Vec<(u64, u64)> = shots.iter().map(|s| (s.start, s.end)).collect();
+        assert_eq!(ranges, [(0, 8), (12, 12), (16, 16)]);
+        assert_eq!(shots[0].frame, Path::new("0.jpg"));
+        assert_eq!(dedup::fold(frames(), 0.0).0.len(), 5);
+
+        // frames decoded in memory are folded as they arrive
+        let mut folder = dedup::Folder::new(0.95);
+        assert_eq!(folder.push(title, "title", 0), None);
+        assert_eq!(folder.push(same, "same", 4), None);
+        let shot = folder.push(other, "other", 8).unwrap();
+        assert_eq!((shot.frame, shot.start, shot.end), ("title", 0, 4));
+        assert_eq!(folder.finish().unwrap().frame, "other");
+        assert_eq!(folder.skipped(), 1);
+    }
+
+    #[test]
+    fn raw_frames() {
+        let line = "[Parsed_showinfo_1 @ 0x5581] n:   3 pts:  46080 pts_time:3.6     duration:512";
+        assert_eq!(frames_iterator::parse_pts_time(line), Some(3.6));
+        assert_eq!(
+            frames_iterator::parse_pts_time("frame=  10 fps=0.0 time=00:00:03.60"),
+            None
+        );
+        let frame = frames_iterator::Frame {
+            pts: 3.6,
+            width: 4,
+            height: 2,
+            format: PixelFormat::Rgb,
+            data: (0..24).collect(),
+        };
+        let image = frame.into_image().to_rgb8();
+        assert_eq!(image.dimensions(), (4, 2));
+        assert_eq!(image.get_pixel(1, 1).0, [15, 16, 17]);
+    }
+
+    #[test]
+    fn subtitle_export() {
+        let cues = [Cue {
+            start: 61_000,
+            end: 64_500,
+            text: " hello there general kenobi".to_string(),
+        }];
+        assert_eq!(
+            subtitles::to_srt(&cues),
+            "1\n00:01:01,000 --> 00:01:04,500\nhello there general kenobi\n\n"
+        );
+        assert_eq!(
+            subtitles::to_vtt(&cues),
+            "WEBVTT\n\n00:01:01.000 --> 00:01:04.500\nhello there general kenobi\n\n"
+        );
+
+        let lines = subtitles::split_cues(&cues, 12, false);
+        let texts: Vec<&str> = lines.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["hello there", "general", "kenobi"]);
+        assert_eq!(lines[0].start, 61_000);
+        assert_eq!(lines[2].end, 64_500);
+
+        let words = subtitles::split_cues(&cues, 0, true);
+        assert_eq!(words.len(), 4);
+        assert!(words.windows(2).all(|w| w[0].end == w[1].start));
+    }
+
+    #[test]
+    fn subtitle_parsing() {
+        let srt = "1\r\n00:00:01,500 --> 00:00:03,000\r\n<i>Hello</i>\r\nworld\r\n\r\n2\r\n00:01:00,000 --> 00:01:02,250\r\nBye\r\n";
+        let cues = subtitles::parse_timed_text(srt);
+        assert_eq!(cues.len(), 2);
+        assert_eq!(
+            cues[0],
+            Cue {
+                start: 1500,
+                end: 3000,
+                text: "Hello world".to_string()
+            }
+        );
+        assert_eq!(cues[1].end, 62_250);
+
+        let vtt = "WEBVTT\n\nNOTE a comment\n\nintro\n00:05.000 --> 00:07.000 align:start\nWelcome <c.yellow>back</c>\n";
+        let cues = subtitles::parse_timed_text(vtt);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start, cues[0].end), (5000, 7000));
+        assert_eq!(cues[0].text, "Welcome back");
+
+        let ass = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:10.50,0:00:12.00,Default,,0,0,0,,{\\i1}Hi,\\Nthere{\\i0}\n";
+        let cues = subtitles::parse_ass(ass);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start, cues[0].end), (10_500, 12_000));
+        assert_eq!(cues[0].text, "Hi, there");
+    }
+
+    /// a word on the first line of the first block
+    fn word(text: &str, x: u32, y: u32, w: u32, h: u32, confidence: f32) -> ocr::OcrWord {
+        return ocr::OcrWord {
+            text: text.to_string(),
+            confidence,
+            block: 1,
+            paragraph: 1,
+            line: 1,
+            bbox: ocr::BoundingBox {
+                left: x,
+                top: y,
+                width: w,
+                height: h,
+            },
+        };
+    }
+    /// one line per word in a 1280x720 frame
+    fn frame(words: &[(&str, u32, u32)]) -> ocr::OcrResult {
+        let words = words
+            .iter()
+            .enumerate()
+            .map(|(i, (text, left, top))| ocr::OcrWord {
+                line: i as u32,
+                ..word(text, *left, *top, 100, 30, 90.0)
+            })
+            .collect();
+        return ocr::OcrResult::from_words(words, 1280, 720);
+    }
+
+    #[test]
+    fn region_filter() {
+        let mut indexer = Indexer::new();
+        indexer.record_ocr(5, 5, &frame(&[("Kubernetes", 100, 20), ("pods", 600, 650)]));
+        indexer.record_ocr(9, 9, &frame(&[("Kubernetes", 1100, 660)]));
+
+        let all = indexer.search_hits(&Query::parse("kubernetes").unwrap());
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].regions[0].describe(), "top left");
+        assert_eq!(all[1].regions[0].describe(), "bottom right");
+
+        let top = indexer.search_hits(&Query::parse("kubernetes region:top").unwrap());
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].timestamp, 5);
+
+        let custom = Query::parse("pods region:0.4,0.8,0.3,0.2").unwrap();
+        assert_eq!(indexer.search_hits(&custom).len(), 1);
+        assert!(Query::parse("pods region:everywhere").is_err());
+    }
+
+    #[test]
+    fn slide_diff() {
+        let mut indexer = Indexer::new();
+        let slides: [(u64, u64, &[&str]); 4] = [
+            (0, 9, &["Agenda", "Introduction"]),
+            (10, 19, &["Agenda", "lntroduction", "Pods"]),
+            (20, 29, &["Agenda", "Introduction", "Pods", "Services"]),
+            (30, 30, &["Summary"]),
+        ];
+        for (start, end, lines) in slides {
+            let words: Vec<(&str, u32, u32)> = lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| (*line, 100, 50 + 60 * i as u32))
+                .collect();
+            indexer.record_ocr_diff(start, end, &frame(&words));
+        }
+        // every bullet is found where it first appeared, misreads don't make it new
+        assert_eq!(indexer.search("introduction"), [0]);
+        assert_eq!(indexer.search("pods"), [10]);
+        assert_eq!(indexer.search("services"), [20]);
+        assert_eq!(indexer.search("lntroduction"), Vec::<u64>::new());
+        let index = indexer.serialize();
+        assert_eq!(index["index"]["agenda"].as_array().unwrap().len(), 1);
+        assert_eq!(index["index"]["agenda"][0]["end"], 29);
+        assert_eq!(index["index"]["summary"][0]["end"], 30);
+        // the frame text is still kept for context
+        assert_eq!(indexer.at(15).frame.unwrap().start, 10);
+    }
+
+    #[test]
+    fn static_overlays() {
+        let record = |mode: OverlayMode| {
+            let mut indexer = Indexer::new();
+            let topics = ["intro", "pods", "services", "volumes", "ingress", "summary"];
+            for (i, topic) in topics.iter().enumerate() {
+                let mut words = vec![(*topic, 400, 300), ("CONFIDENTIAL", 1100, 660)];
+                // a ticker moving along the bottom
+                words.push(("LIVE", 100 + 150 * i as u32, 600));
+                // a logo in the corner of every third frame
+                if i % 3 == 0 {
+                    words.push(("ACME", 20, 20));
+                }
+                if *topic == "summary" {
+                    words.push(("confidential", 400, 400));
+                    words.push(("acme", 600, 300));
+                }
+                let start = i as u64 * 10;
+                indexer.record_ocr(start, start + 9, &frame(&words));
+            }
+            indexer.suppress_overlays(0.6, 0.3, mode);
+            indexer
+        };
+        let indexer = record(OverlayMode::Downweight);
+        let overlays = &indexer.metadata.overlays;
+        assert_eq!(overlays.len(), 3);
+        assert_eq!(overlays[0].word, "confidential");
+        assert_eq!(overlays[0].share, 1.0);
+        assert_eq!(overlays[0].region.unwrap().describe(), "bottom right");
+        assert_eq!(overlays[1].word, "live");
+        assert!(overlays[1].region.is_none());
+        assert_eq!(overlays[2].word, "acme");
+        assert!((overlays[2].share - 1.0 / 3.0).abs() < 0.01);
+        assert_eq!(overlays[2].region.unwrap().describe(), "top left");
+
+        // the banner still matches, but below the slide that talks about it
+        let hits = indexer.search_hits(&Query::parse("confidential").unwrap());
+        assert_eq!(hits.len(), 6);
+        assert_eq!(hits[0].timestamp, 50);
+        assert!(hits[1].score < 1.0);
+
+        // overlays are only dropped where they sit
+        let indexer = record(OverlayMode::Drop);
+        assert_eq!(indexer.search("confidential"), [50]);
+        assert_eq!(indexer.search("acme"), [50]);
+        assert_eq!(indexer.search("live"), Vec::<u64>::new());
+        assert_eq!(indexer.search("pods"), [10]);
+        // too few frames to tell
+        let mut short = Indexer::new();
+        short.record_ocr(0, 9, &frame(&[("CONFIDENTIAL", 1100, 660)]));
+        assert!(short
+            .suppress_overlays(0.6, 0.3, OverlayMode::Drop)
+            .is_empty());
+    }
+
+    #[test]
+    fn masks() {
+        let areas = mask::parse_areas("0.1,0.1,0.5,0.5; 600,20,40,300px;").unwrap();
+        assert_eq!(areas.len(), 2);
+        assert!(matches!(areas[1], Area::Pixels(_)));
+        assert!(mask::parse_areas("1,2,3px").is_err());
+        assert!(mask::parse_areas("projector").is_err());
+        // clipped to the frame
+        let bbox = areas[1].to_bbox(320, 180);
+        assert_eq!(
+            (bbox.left, bbox.top, bbox.width, bbox.height),
+            (320, 20, 0, 160)
+        );
+        let top = "top".parse::<Area>().unwrap().to_bbox(300, 90);
+        assert_eq!((top.width, top.height), (300, 30));
+
+        // a chat panel on the right, text everywhere else
+        let frame = DynamicImage::ImageLuma8(GrayImage::from_fn(200, 100, |x, y| {
+            Luma([if (x / 4 + y / 4) % 2 == 0 { 0 } else { 255 }])
+        }));
+        let chat = Mask {
+            include: Vec::new(),
+            exclude: mask::parse_areas("150,0,50,100px").unwrap(),
+        };
+        let blanked = chat.blank(frame.clone()).to_luma8();
+        assert_eq!(blanked.get_pixel(160, 10), blanked.get_pixel(190, 90));
+        assert_eq!(blanked.get_pixel(0, 0)[0], 0);
+        assert!(textdetect::text_regions(&chat.view(&frame))
+            .iter()
+            .all(|r| r.left + r.width <= 150));
+
+        let projector = Mask {
+            include: mask::parse_areas("0.25,0.25,0.5,0.5").unwrap(),
+            exclude: Vec::new(),
+        };
+        let view = projector.view(&frame);
+        assert_eq!((view.width(), view.height()), (100, 50));
+        assert_eq!(Mask::default().areas(200, 100)[0].width, 200);
+        let preview = draw::draw_mask(
+            &frame,
+            &Mask {
+                exclude: chat.exclude,
+                ..projector
+            },
+        );
+        assert_eq!(preview.get_pixel(51, 26).0, [0, 200, 0]);
+        assert!(preview.get_pixel(170, 50)[0] > preview.get_pixel(170, 50)[1]);
+
+        // per profile and per video options, flags still win
+        let config = "dedup_threshold = 0.9\n\
+            [profile.stream]\nexclude = 0.75,0,0.25,1\n\
+            [video.lecture]\nroi = 0.1,0.1,0.8,0.7\ndedup_threshold = 0.8\n";
+        let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let (options, _) = Options::parse_with(config, &args(&["talk.mp4"])).unwrap();
+        assert!(options.mask.is_empty());
+        assert_eq!(options.dedup_threshold, 0.9);
+        let (options, _) = Options::parse_with(
+            config,
+            &args(&["videos/lecture.mp4", "--profile", "stream"]),
+        )
+        .unwrap();
+        assert_eq!(options.mask.include.len(), 1);
+        assert_eq!(options.mask.exclude.len(), 1);
+        assert_eq!(options.dedup_threshold, 0.8);
+        let (options, _) = Options::parse_with(
+            config,
+            &args(&[
+                "lecture.mp4",
+                "--dedup-threshold",
+                "0.5",
+                "--roi",
+                "10,10,100,100px",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(options.dedup_threshold, 0.5);
+        assert!(matches!(options.mask.include[..], [Area::Pixels(_)]));
+        assert!(Options::parse_with(config, &args(&["--profile", "lecture"])).is_err());
+    }
+
+    #[test]
+    fn burned_in_captions() {
+        let band = ocr::OcrResult::from_words(
+            vec![
+                word("Hello", 0, 0, 0, 0, 91.0),
+                word("|~", 0, 0, 0, 0, 20.0),
+                ocr::OcrWord {
+                    line: 2,
+                    ..word("'", 0, 0, 0, 0, 60.0)
+                },
+            ],
+            1280,
+            180,
+        );
+        assert_eq!(captions::caption_text(&band), "Hello");
+
+        let frames: Vec<(u64, String)> = [
+            "",
+            "Welcome to the course",
+            "Welcome to the course",
+            "We1come to the course",
+            "Today: ownership",
+            "Today: ownership",
+            "",
+            "Thanks for watching",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, text)| (i as u64 * 500, text.to_string()))
+        .collect();
+        let cues = captions::stabilize(&frames, 500);
+        assert_eq!(
+            cues,
+            [
+                Cue {
+                    start: 500,
+                    end: 2000,
+                    text: "Welcome to the course".to_string()
+                },
+                Cue {
+                    start: 2000,
+                    end: 3000,
+                    text: "Today: ownership".to_string()
+                },
+                Cue {
+                    start: 3500,
+                    end: 4000,
+                    text: "Thanks for watching".to_string()
+                },
+            ]
+        );
+        assert!(subtitles::to_srt(&cues).starts_with("1\n00:00:00,500 --> 00:00:02,000\n"));
+
+        let mut indexer = Indexer::new();
+        for cue in &cues {
+            indexer.record(Source::Caption, cue.start / 1000, cue.end / 1000, &cue.text);
+        }
+        let hits = indexer.search_hits(&Query::parse("ownership").unwrap());
+        assert_eq!(hits[0].timestamp, 2);
+        assert_eq!(hits[0].snippets[0].source.name(), "caption");
+    }
+
+    #[test]
+    fn hit_refinement() {
+        // the text is on screen from frame 130 to frame 251
+        let mut probes = 0;
+        let mut shows = |frame: u64| {
+            probes += 1;
+            Ok((130..=251).contains(&frame))
+        };
+        assert_eq!(refine::first_visible(90, 149, &mut shows), Ok(130));
+        assert_eq!(refine::last_visible(240, 299, &mut shows), Ok(251));
+        assert!(probes <= 14);
+        assert_eq!(refine::first_visible(130, 149, |_| Ok(true)), Ok(130));
+        assert_eq!(refine::last_visible(240, 299, |_| Ok(true)), Ok(299));
+        assert_eq!(refine::last_visible(240, 200, |_| Ok(true)), Ok(240));
+        let failed = refine::first_visible(0, 10, |_| Err("no ffmpeg".to_string()));
+        assert!(failed.is_err());
+        assert_eq!(crate::format_precise(62.4333), "00:01:02.433");
+
+        // keyframes at 0, 4 and 9 seconds, the slide is shown from the second one on
+        let mut indexer = Indexer::new();
+        indexer.record_ocr_diff(0, 3, &frame(&[("Agenda", 100, 50)]));
+        indexer.record_ocr_diff(4, 8, &frame(&[("Ownership", 100, 50)]));
+        indexer.record_ocr_diff(9, 12, &frame(&[("Borrowing", 100, 50)]));
+        indexer.record(Source::Asr, 5, 7, "ownership means one owner");
+        let query = Query::parse("ownership").unwrap();
+        let hits = indexer.search_hits(&query);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].end, Some(8));
+        assert_eq!(hits[1].end, None);
+        assert_eq!(indexer.frames_around(4, 8), (Some(3), Some(9)));
+        assert_eq!(indexer.frames_around(0, 3), (None, Some(4)));
+        assert_eq!(indexer.frames_around(9, 12), (Some(8), None));
+        assert_eq!(
+            indexer.indexed_terms("ownership of pointers"),
+            HashSet::from(["ownership".to_string()])
+        );
+    }
+
+    #[test]
+    fn layout_ranking() {
+        // (text, top, height) in a 1280x720 frame, one line each
+        let slide = |lines: &[(&str, u32, u32)]| {
+            let words = lines
+                .iter()
+                .enumerate()
+                .map(|(i, (text, top, height))| ocr::OcrWord {
+                    line: i as u32,
+                    ..word(text, 100, *top, 200, *height, 90.0)
+                })
+                .collect();
+            ocr::OcrResult::from_words(words, 1280, 720)
+        };
+        let mut indexer = Indexer::new();
+        // a footnote, then body text, then a large title halfway down a section slide
+        indexer.record_ocr(
+            0,
+            9,
+            &slide(&[
+                ("Intro", 40, 40),
+                ("lifetimes", 300, 40),
+                ("borrowing", 680, 20),
+            ]),
+        );
+        indexer.record_ocr(
+            10,
+            19,
+            &slide(&[("Agenda", 300, 40), ("borrowing", 400, 40)]),
+        );
+        indexer.record_ocr(
+            20,
+            29,
+            &slide(&[("Borrowing", 330, 90), ("rules", 500, 40)]),
+        );
+        indexer.record(Source::Asr, 30, 35, "borrowing again");
+
+        let index = indexer.serialize();
+        assert_eq!(index["index"]["borrowing"][0]["size"], 0.5);
+        assert_eq!(index["index"]["borrowing"][2]["size"], 2.25);
+        let hits = indexer.search_hits(&Query::parse("borrowing").unwrap());
+        let order: Vec<u64> = hits.iter().map(|hit| hit.timestamp).collect();
+        // the title first, the footnote last
+        assert_eq!(order, [20, 10, 30, 0]);
+        assert_eq!(hits[1].score, hits[2].score);
+
+        let title = Query::parse("title:borrowing").unwrap();
+        assert_eq!(title.all_text(), "borrowing");
+        let titles: Vec<u64> = indexer
+            .search_hits(&title)
+            .iter()
+            .map(|h| h.timestamp)
+            .collect();
+        assert_eq!(titles, [20]);
+        // top of the frame counts as a title too, even at body size
+        assert_eq!(
+            indexer
+                .search_hits(&Query::parse("title:intro").unwrap())
+                .len(),
+            1
+        );
+        assert!(indexer
+            .search_hits(&Query::parse("title:lifetimes").unwrap())
+            .is_empty());
+        assert_eq!(
+            indexer.search_hits(&Query::parse("title:borrowing rules").unwrap())[0].timestamp,
+            20
+        );
+    }
+
+    #[test]
+    fn ensemble_member_models() {
+        let members =
+            ensemble::parse_members("tesseract-cli:3:best,tesseract-cli:12:fast").unwrap();
+        let args: Vec<Vec<String>> = members
+            .iter()
+            .map(|member| {
+                let engine = ocr::TesseractCliEngine::new(&member.settings(&Default::default()));
+                let command = engine.command(Path::new("frame.png"));
+                command
+                    .get_args()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect()
+            })
+            .collect();
+        // each member runs on its own model, even in the same process
+        for (args, (psm, model)) in args.iter().zip([("3", "best"), ("12", "fast")]) {
+            let tessdata_dir = format!("models/traineddata/tessdata_{}", model);
+            assert!(args
+                .windows(2)
+                .any(|pair| pair == ["--tessdata-dir", tessdata_dir.as_str()]));
+            assert!(args.windows(2).any(|pair| pair == ["--psm", psm]));
+        }
+    }
+    #[test]
+    fn ocr_ensemble() {
+        let reading = |words: &[(&str, f32, u32)]| {
+            let words = words
+                .iter()
+                .map(|&(text, confidence, line)| ocr::OcrWord {
+                    line,
+                    ..word(text, 0, 0, 0, 0, confidence)
+                })
+                .collect();
+            ocr::OcrResult::from_words(words, 960, 540)
+        };
+        let readings = [
+            reading(&[
+                ("Introductlon", 60.0, 1),
+                ("to", 95.0, 1),
+                ("Kubernetes", 90.0, 1),
+                ("Lecture", 90.0, 2),
+                ("3", 90.0, 2),
+            ]),
+            reading(&[
+                ("Introduction", 90.0, 1),
+                ("to", 95.0, 1),
+                ("Kubernetes", 88.0, 1),
+                ("Lecture", 50.0, 2),
+                ("3", 50.0, 2),
+                ("spring", 50.0, 2),
+            ]),
+            reading(&[
+                ("introduction", 85.0, 1),
+                ("to", 95.0, 1),
+                ("Kubemetes", 70.0, 1),
+                ("|", 20.0, 1),
+                ("Lecture", 40.0, 2),
+                ("3", 40.0, 2),
+                ("spring", 40.0, 2),
+            ]),
+        ];
+        // misreadings are outvoted, a stray mark only one member saw is dropped
+        // and a word the most confident member missed is kept, on its line
+        let voted = ensemble::vote(&readings);
+        assert_eq!(voted.text, "Introduction to Kubernetes\nLecture 3 spring");
+        assert_eq!((voted.width, voted.height), (960, 540));
+        assert_eq!(ensemble::vote(&readings[..1]).text, readings[0].text);
+        assert!(ensemble::vote(&[]).words.is_empty());
+
+        assert_eq!(eval::cer("Lecture 3", "Lecture  3"), 0.0);
+        assert_eq!(eval::cer("abcd e", "abxd e"), 1.0 / 6.0);
+        assert_eq!(eval::wer("the owner goes out", "the owner qoes out"), 0.25);
+        assert_eq!(eval::wer("", ""), 0.0);
+        let pairs = eval::align_with(&['a', 'b', 'c'], &['a', 'c', 'd'], |x, y| {
+            f32::from(u8::from(x != y))
+        });
+        assert_eq!(
+            pairs,
+            [
+                (Some(0), Some(0)),
+                (Some(1), None),
+                (Some(2), Some(1)),
+                (None, Some(2))
+            ]
+        );
+
+        let members =
+            ensemble::parse_members("leptess:3:best, tesseract-cli:12:/opt/tessdata").unwrap();
+        assert_eq!(members[1].to_string(), "tesseract-cli:12:/opt/tessdata");
+        let settings = members[0].settings(&ocr::OcrSettings::default());
+        assert_eq!(settings.psm, 3);
+        assert_eq!(
+            settings.tessdata_dir.as_deref(),
+            Some("models/traineddata/tessdata_best")
+        );
+        assert_eq!(ensemble::parse_members("default").unwrap().len(), 3);
+        assert!(ensemble::parse_members("leptess:3").is_err());
+        assert!(ensemble::parse_members("paddle:3:best").is_err());
+        let (options, _) = Options::parse_with("ocr_ensemble = default\n", &[]).unwrap();
+        assert_eq!(options.ocr_ensemble.len(), 3);
+    }
+
+    #[test]
+    fn grammar_corrections() {
+        let mut indexer = Indexer::new();
+        indexer.record(Source::Asr, 10, 14, "so we was go to the mall");
+        indexer.record(Source::Asr, 10, 12, "and then home");
+        let words = ["Recieve", "the", "package"]
+            .iter()
+            .map(|text| word(text, 0, 0, 0, 0, 90.0))
+            .collect();
+        indexer.record_ocr(20, 25, &ocr::OcrResult::from_words(words, 960, 540));
+
+        let passages = indexer.passage_texts(Source::Asr);
+        assert_eq!(passages.len(), 2);
+        assert_eq!(passages[0], (10, 0, "so we was go to the mall".to_string()));
+        indexer.record_correction(10, 0, "so we were going to the mall");
+        // an unchanged or empty correction is not kept
+        indexer.record_correction(10, 1, "and then home");
+        indexer.record_correction(20, 0, "");
+        let segments = indexer.at(10).segments;
+        assert_eq!(
+            segments[0].corrected.as_deref(),
+            Some("so we were going to the mall")
+        );
+        assert!(segments[1].corrected.is_none());
+
+        // both forms are found, each shown in its own form
+        let hits = indexer.search_hits(&Query::parse("going").unwrap());
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].snippets[0].highlight("[", "]"),
+            "so we were [going] to the mall"
+        );
+        let hits = indexer.search_hits(&Query::parse("was").unwrap());
+        assert_eq!(
+            hits[0].snippets[0].highlight("[", "]"),
+            "so we [was] go to the mall"
+        );
+
+        let (_, i, text) = indexer.passage_texts(Source::Ocr).remove(0);
+        assert_eq!(text, "Recieve the package");
+        indexer.record_correction(20, i, "Receive the package");
+        let hits = indexer.search_hits(&Query::parse("receive").unwrap());
+        assert_eq!((hits[0].timestamp, hits[0].end), (20, Some(25)));
+        assert_eq!(
+            indexer.search_hits(&Query::parse("recieve").unwrap())[0].timestamp,
+            20
+        );
+    }
+
+    #[test]
+    fn spell_correction() {
+        let mut dictionary = SymSpell::new(2);
+        for word in ["kubernetes", "clusters", "deploy"] {
+            dictionary.add(word);
+        }
+        assert!(dictionary.contains("clusters"));
+        assert!(!dictionary.contains("cluster"));
+        assert_eq!(dictionary.lookup("kubernetcs", 2), [("kubernetes", 1)]);
+        assert_eq!(dictionary.lookup("kubrnetcs", 2), [("kubernetes", 2)]);
+        assert!(dictionary.lookup("kubrnetcs", 1).is_empty());
+        assert_eq!(spell::max_distance("pods"), 0);
+        assert_eq!(spell::max_distance("deploy"), 1);
+        assert_eq!(spell::max_distance("kubernetcs"), 2);
+
+        // one line of text
+        let frame = |text: &str| {
+            let words = text.split(' ').map(|text| word(text, 0, 0, 0, 0, 90.0));
+            ocr::OcrResult::from_words(words.collect(), 960, 540)
+        };
+        let mut indexer = Indexer::new();
+        indexer.record_ocr(90, 120, &frame("Deploying Kubernetcs 2023"));
+        indexer.record_ocr(500, 510, &frame("Kubernetcs on Grafama"));
+        indexer.record(Source::Asr, 100, 104, "today we deploy kubernetes");
+        let corrections = indexer.correct_from_speech(&["Grafana".to_string()], 30);
+        // the second kubernetcs is too far from where it was said
+        assert_eq!(
+            corrections,
+            [
+                Correction {
+                    timestamp: 90,
+                    read: "kubernetcs".to_string(),
+                    corrected: "kubernetes".to_string(),
+                    distance: 1,
+                    spoken_at: Some(100),
+                },
+                Correction {
+                    timestamp: 500,
+                    read: "grafama".to_string(),
+                    corrected: "grafana".to_string(),
+                    distance: 1,
+                    spoken_at: None,
+                },
+            ]
+        );
+        let hits = indexer.search_hits(&Query::parse("kubernetes").unwrap());
+        let found: Vec<u64> = hits.iter().map(|hit| hit.timestamp).collect();
+        assert_eq!(found, [100, 90]);
+        assert_eq!(hits[1].end, Some(120));
+        // the read form is still indexed, the corrected one ranks below it
+        let read = indexer.search_hits(&Query::parse("kubernetcs").unwrap());
+        assert_eq!(read.len(), 2);
+        assert_eq!(hits[1].score, read[0].score * 0.5);
+        assert!(Indexer::new().correct_from_speech(&[], 30).is_empty());
+    }
+
+    #[test]
+    fn glossary() {
+        let glossary = Glossary::parse(
+            "# product names\nKubeFlow\n  Argo CD \n\nC#\nJIRA-\\d\\d\\d\\d\nArgo Rollouts\n",
+        );
+        assert_eq!(
+            glossary.terms,
+            ["KubeFlow", "Argo CD", "C#", "Argo Rollouts"]
+        );
+        assert_eq!(glossary.patterns, ["JIRA-\\d\\d\\d\\d"]);
+        assert_eq!(glossary.prompt(), "KubeFlow, Argo CD, C#, Argo Rollouts.");
+        assert_eq!(
+            glossary.words(),
+            ["KubeFlow", "Argo", "CD", "C#", "Rollouts"]
+        );
+        assert!(Glossary::parse("# nothing yet\n").is_empty());
+        assert_eq!(Glossary::default().prompt(), "");
+        let long = Glossary::parse(&"Kubernetes\n".repeat(100));
+        assert!(long.prompt().len() <= 600);
+
+        // every backend sets the user files before the language is loaded
+        let settings = ocr::OcrSettings {
+            user_words: Some("dump/glossary.user-words".to_string()),
+            user_patterns: Some("dump/glossary.user-patterns".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.variables(),
+            [
+                ("user_words_file", "dump/glossary.user-words".to_string()),
+                (
+                    "user_patterns_file",
+                    "dump/glossary.user-patterns".to_string()
+                )
+            ]
+        );
+        assert!(ocr::OcrSettings::default().variables().is_empty());
+        let engine = ocr::TesseractCliEngine::new(&settings);
+        let command = engine.command(Path::new("frame.png"));
+        let args: Vec<&str> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert!(args
+            .windows(2)
+            .any(|pair| pair == ["-c", "user_words_file=dump/glossary.user-words"]));
+
+        let mut indexer = Indexer::new();
+        indexer.record(Source::Ocr, 0, 10, "Deploying with Argo CD");
+        indexer.record(Source::Ocr, 10, 20, "Argo CO sync waves");
+        indexer.record(Source::Asr, 2, 6, "so argo cd watches the repo");
+        indexer.record(Source::Asr, 12, 16, "and cube flow runs the pipeline");
+        indexer.record(Source::Subtitle, 12, 16, "and KubeFlow runs the pipeline");
+        indexer.mark_glossary(&glossary.terms[..2]);
+        assert_eq!(
+            indexer.metadata.glossary,
+            [
+                GlossaryTerm {
+                    term: "KubeFlow".to_string(),
+                    read: 0,
+                    heard: 0,
+                },
+                GlossaryTerm {
+                    term: "Argo CD".to_string(),
+                    read: 1,
+                    heard: 1,
+                },
+            ]
+        );
+        let terms = indexer.glossary_terms(&Query::parse("argo cd sync").unwrap());
+        assert_eq!(terms.len(), 1);
+        assert_eq!(terms[0].term, "Argo CD");
+        assert!(indexer
+            .glossary_terms(&Query::parse("argo").unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn asr_engines() {
+        let json = r#"{
+            "result": { "language": "de" },
+            "transcription": [
+                {
+                    "timestamps": { "from": "00:00:00,000", "to": "00:00:02,500" },
+                    "offsets": { "from": 0, "to": 2500 },
+                    "text": " Hallo zusammen",
+                    "tokens": [
+                        { "text": "[_BEG_]", "offsets": { "from": 0, "to": 0 }, "p": 0.9 },
+                        { "text": " Hallo", "offsets": { "from": 0, "to": 1200 }, "p": 0.95 },
+                        { "text": " zusammen", "offsets": { "from": 1200, "to": 2500 }, "p": 0.8 }
+                    ]
+                },
+                { "offsets": { "from": 2500, "to": 4000 }, "text": " heute Rust" }
+            ]
+        }"#;
+        let (segments, language) = asr::parse_whisper_json(json).unwrap();
+        assert_eq!(language.as_deref(), Some("de"));
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].start, segments[0].end), (0, 2500));
+        assert_eq!(
+            segments[0].tokens,
+            [
+                asr::Token {
+                    text: " Hallo".to_string(),
+                    start: 0,
+                    end: 1200,
+                    prob: 0.95,
+                },
+                asr::Token {
+                    text: " zusammen".to_string(),
+                    start: 1200,
+                    end: 2500,
+                    prob: 0.8,
+                },
+            ]
+        );
+        // plain -oj output has no tokens
+        assert!(segments[1].tokens.is_empty());
+        // a transcript as written by `main -ojf`
+        let full = std::fs::read_to_string("data/asr/whisper_cpp_full.json").unwrap();
+        let (full, language) = asr::parse_whisper_json(&full).unwrap();
+        assert_eq!(language.as_deref(), Some("de"));
+        assert_eq!(full.len(), 1);
+        assert_eq!((full[0].start, full[0].end), (0, 3200));
+        let texts: Vec<&str> = full[0].tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [" Hallo", " zusammen", ",", " heute", " Kub", "ernetes", "."]
+        );
+        assert_eq!(
+            (full[0].tokens[5].start, full[0].tokens[5].end),
+            (2300, 3120)
+        );
+        assert!((full[0].tokens[4].prob - 0.843017).abs() < 1e-6);
+        assert!(asr::parse_whisper_json("not json").is_err());
+        assert!(asr::parse_whisper_json(r#"{"transcription": [{"text": "x"}]}"#).is_err());
+
+        assert_eq!("whisper-cpp".parse(), Ok(asr::AsrBackend::WhisperCpp));
+        assert_eq!("mock".parse(), Ok(asr::AsrBackend::Mock));
+        assert!("vosk".parse::<asr::AsrBackend>().is_err());
+        let mut options = Options::default();
+        options.apply_file("asr_backend = mock").unwrap();
+        // the mock doesn't fall back to the whisper model
+        assert!(asr::make_engine(&options.asr).is_err());
+        options.apply_file("asr_mock_file = \"talk.srt\"").unwrap();
+        assert_eq!(options.asr.mock_file.as_deref(), Some("talk.srt"));
+        assert_eq!(options.asr.model, asr::AsrSettings::default().model);
+
+        // the rest of the pipeline runs on the mock's segments
+        let mut engine = asr::MockEngine::new(segments, "de");
+        let segments = engine.transcribe(Path::new("audio.wav")).unwrap();
+        assert_eq!(engine.language(), "de");
+        let mut indexer = Indexer::new();
+        let cues = crate::record_segments(&segments, &mut indexer);
+        assert_eq!(
+            cues[1],
+            Cue {
+                start: 2500,
+                end: 4000,
+                text: "heute Rust".to_string(),
+            }
+        );
+        let hits = indexer.search_hits(&Query::parse("rust").unwrap());
+        assert_eq!(hits[0].timestamp, 2);
+        assert_eq!(hits[0].snippets[0].source, Source::Asr);
+    }
 }
@@ -0,0 +1,202 @@
+use image::{imageops, DynamicImage, GrayImage};
+
+use crate::ocr::BoundingBox;
+
+/// frames are analyzed at this width
+const DETECTION_WIDTH: u32 = 640;
+/// a pixel is an edge if its gradient is at least this strong
+const EDGE_THRESHOLD: i32 = 48;
+/// edges this close horizontally are joined, so the letters of a line become one component
+const JOIN_DISTANCE: u32 = 6;
+/// share of edge pixels in the box of a text component
+const EDGE_DENSITY: (f32, f32) = (0.08, 0.9);
+/// margin added around every region before cropping, in detection pixels
+const MARGIN: u32 = 4;
+
+/// finds the likely text regions of a frame from the density of sharp edges.
+/// text is a run of short, strong edges packed into a wide box,
+/// the boxes are returned in frame coordinates, top to bottom
+pub fn text_regions(frame: &DynamicImage) -> Vec<BoundingBox> {
+    let scale = (frame.width() as f32 / DETECTION_WIDTH as f32).max(1.0);
+    let gray = match scale > 1.0 {
+        true => imageops::resize(
+            &frame.to_luma8(),
+            DETECTION_WIDTH,
+            ((frame.height() as f32 / scale) as u32).max(1),
+            imageops::FilterType::Triangle,
+        ),
+        false => frame.to_luma8(),
+    };
+    let edges = edge_map(&gray);
+    let (width, height) = gray.dimensions();
+    let joined = join_horizontally(&edges, width, JOIN_DISTANCE);
+    let candidates = components(&joined, width, height)
+        .into_iter()
+        .filter(|bbox| looks_like_text(bbox, &edges, width, height))
+        .map(|bbox| {
+            let (left, top) = (
+                bbox.left.saturating_sub(MARGIN),
+                bbox.top.saturating_sub(MARGIN),
+            );
+            let right = (bbox.left + bbox.width + MARGIN).min(width);
+            let bottom = (bbox.top + bbox.height + MARGIN).min(height);
+            BoundingBox {
+                left,
+                top,
+                width: right - left,
+                height: bottom - top,
+            }
+        })
+        .collect();
+    let mut regions: Vec<BoundingBox> = merge_overlapping(candidates)
+        .into_iter()
+        .map(|bbox| {
+            let left = (bbox.left as f32 * scale) as u32;
+            let top = (bbox.top as f32 * scale) as u32;
+            BoundingBox {
+                left,
+                top,
+                width: ((bbox.width as f32 * scale) as u32).min(frame.width() - left),
+                height: ((bbox.height as f32 * scale) as u32).min(frame.height() - top),
+            }
+        })
+        .collect();
+    regions.sort_by_key(|bbox| (bbox.top, bbox.left));
+    return regions;
+}
+
+/// true where the horizontal or vertical gradient is strong
+fn edge_map(gray: &GrayImage) -> Vec<bool> {
+    let (width, height) = gray.dimensions();
+    let pixel = |x: u32, y: u32| gray.get_pixel(x, y)[0] as i32;
+    let mut edges = vec![false; (width * height) as usize];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let dx = (pixel(x + 1, y) - pixel(x - 1, y)).abs();
+            let dy = (pixel(x, y + 1) - pixel(x, y - 1)).abs();
+            edges[(y * width + x) as usize] = dx.max(dy) >= EDGE_THRESHOLD;
+        }
+    }
+    return edges;
+}
+
+/// fills horizontal gaps between edges of the same row up to `distance` pixels wide
+fn join_horizontally(edges: &[bool], width: u32, distance: u32) -> Vec<bool> {
+    let mut joined = edges.to_vec();
+    for (y, row) in edges.chunks(width as usize).enumerate() {
+        let mut last_edge: Option<usize> = None;
+        for (x, edge) in row.iter().enumerate() {
+            if !edge {
+                continue;
+            }
+            if let Some(last) = last_edge.filter(|last| x - last <= distance as usize + 1) {
+                let offset = y * width as usize;
+                joined[offset + last..offset + x]
+                    .iter_mut()
+                    .for_each(|p| *p = true);
+            }
+            last_edge = Some(x);
+        }
+    }
+    return joined;
+}
+
+/// merges boxes until none overlap, the lines of a paragraph end up in one box
+fn merge_overlapping(mut boxes: Vec<BoundingBox>) -> Vec<BoundingBox> {
+    let overlap = |a: &BoundingBox, b: &BoundingBox| {
+        a.left < b.left + b.width
+            && b.left < a.left + a.width
+            && a.top < b.top + b.height
+            && b.top < a.top + a.height
+    };
+    let mut merged = true;
+    while merged {
+        merged = false;
+        'search: for i in 0..boxes.len() {
+            for j in i + 1..boxes.len() {
+                if overlap(&boxes[i], &boxes[j]) {
+                    let (a, b) = (boxes[i], boxes.swap_remove(j));
+                    let (left, top) = (a.left.min(b.left), a.top.min(b.top));
+                    let right = (a.left + a.width).max(b.left + b.width);
+                    let bottom = (a.top + a.height).max(b.top + b.height);
+                    boxes[i] = BoundingBox {
+                        left,
+                        top,
+                        width: right - left,
+                        height: bottom - top,
+                    };
+                    merged = true;
+                    break 'search;
+                }
+            }
+        }
+    }
+    return boxes;
+}
+
+/// bounding boxes of the 4-connected components of the mask
+fn components(mask: &[bool], width: u32, height: u32) -> Vec<BoundingBox> {
+    let mut seen = vec![false; mask.len()];
+    let mut boxes = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..mask.len() {
+        if !mask[start] || seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        stack.push(start);
+        let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+        while let Some(i) = stack.pop() {
+            let (x, y) = ((i as u32) % width, (i as u32) / width);
+            (left, top) = (left.min(x), top.min(y));
+            (right, bottom) = (right.max(x), bottom.max(y));
+            let mut neighbours = Vec::with_capacity(4);
+            if x > 0 {
+                neighbours.push(i - 1);
+            }
+            if x + 1 < width {
+                neighbours.push(i + 1);
+            }
+            if y > 0 {
+                neighbours.push(i - width as usize);
+            }
+            if y + 1 < height {
+                neighbours.push(i + width as usize);
+            }
+            for n in neighbours {
+                if mask[n] && !seen[n] {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        boxes.push(BoundingBox {
+            left,
+            top,
+            width: right - left + 1,
+            height: bottom - top + 1,
+        });
+    }
+    return boxes;
+}
+
+/// text lines are wider than tall, not too big, and neither empty nor solid with edges
+fn looks_like_text(bbox: &BoundingBox, edges: &[bool], width: u32, height: u32) -> bool {
+    if bbox.height < 6 || bbox.height > height / 4 || bbox.width < bbox.height {
+        return false;
+    }
+    if bbox.width > width * 95 / 100 && bbox.height > height / 8 {
+        return false;
+    }
+    let mut count = 0;
+    for y in bbox.top..bbox.top + bbox.height {
+        let row = (y * width) as usize;
+        let from = row + bbox.left as usize;
+        count += edges[from..from + bbox.width as usize]
+            .iter()
+            .filter(|e| **e)
+            .count();
+    }
+    let density = count as f32 / (bbox.width * bbox.height) as f32;
+    return (EDGE_DENSITY.0..=EDGE_DENSITY.1).contains(&density);
+}
@@ -11,6 +11,13 @@ pub mod ffmpeg_utils {
         pub fps: u64,
     }
     #[derive(Debug)]
+    pub struct SubtitleStream {
+        /// position among the subtitle streams, as used by `-map 0:s:<n>`
+        pub position: usize,
+        pub codec: String,
+        pub language: Option<String>,
+    }
+    #[derive(Debug)]
     pub enum FFmpegResult {
         Success(String),
         Failure(String),
@@ -21,23 +28,106 @@ pub mod ffmpeg_utils {
         Failure(String),
     }
 
-    /// uses ffmpeg to extract keyframes from a video
-    pub fn extract_keyframes(video_path: &Path, output_path: &Path) -> FFmpegResult {
+    /// which frames are extracted for OCR
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Sampling {
+        /// the codec's keyframes, cheap but sparse in screen recordings
+        Keyframes,
+        /// one frame every n seconds
+        Interval(f64),
+        /// frames whose scene change score (0 to 1) is above the threshold
+        Scene(f64),
+        /// scene changes, but never more than `max_gap` seconds apart
+        Hybrid { scene: f64, max_gap: f64 },
+    }
+
+    impl std::str::FromStr for Sampling {
+        type Err = String;
+        /// `keyframes`, `interval:<seconds>`, `scene:<threshold>` or `hybrid:<threshold>:<seconds>`
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let parts: Vec<&str> = s.trim().split(':').collect();
+            let number = |i: usize, default: f64| match parts.get(i) {
+                Some(part) => part
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|n| *n > 0.0)
+                    .ok_or_else(|| format!("invalid sampling argument '{}'", part)),
+                None => Ok(default),
+            };
+            match parts[0] {
+                "keyframes" => Ok(Sampling::Keyframes),
+                "interval" => Ok(Sampling::Interval(number(1, 5.0)?)),
+                "scene" => Ok(Sampling::Scene(number(1, 0.3)?)),
+                "hybrid" => Ok(Sampling::Hybrid {
+                    scene: number(1, 0.3)?,
+                    max_gap: number(2, 10.0)?,
+                }),
+                _ => Err(format!(
+                    "unknown sampling mode '{}', expected keyframes, interval, scene or hybrid",
+                    s
+                )),
+            }
+        }
+    }
+
+    impl Sampling {
+        /// ffmpeg arguments placed before the input
+        pub fn input_args(&self) -> Vec<String> {
+            match self {
+                Sampling::Keyframes => vec!["-skip_frame".to_string(), "nokey".to_string()],
+                _ => Vec::new(),
+            }
+        }
+        /// ffmpeg arguments placed after the input, selecting the frames.
+        /// `extra_filters` run on the selected frames
+        pub fn output_args(&self, extra_filters: &[&str]) -> Vec<String> {
+            let select = match self {
+                Sampling::Keyframes => None,
+                Sampling::Interval(seconds) => Some(format!("fps=1/{}", seconds)),
+                // the first frame is always kept
+                Sampling::Scene(scene) => Some(format!("select='eq(n,0)+gt(scene,{})'", scene)),
+                Sampling::Hybrid { scene, max_gap } => Some(format!(
+                    "select='eq(n,0)+gt(scene,{})+gte(t-prev_selected_t,{})'",
+                    scene, max_gap
+                )),
+            };
+            let filters: Vec<String> = select
+                .into_iter()
+                .chain(extra_filters.iter().map(|f| f.to_string()))
+                .collect();
+            let mut args = Vec::new();
+            if !filters.is_empty() {
+                args.extend(["-vf".to_string(), filters.join(",")]);
+            }
+            if !matches!(self, Sampling::Interval(_)) {
+                args.extend(["-fps_mode".to_string(), "passthrough".to_string()]);
+            }
+            return args;
+        }
+    }
+
+    /// uses ffmpeg to extract frames from a video, named after their frame number at `fps`
+    pub fn extract_frames(
+        video_path: &Path,
+        output_path: &Path,
+        sampling: Sampling,
+        fps: u64,
+    ) -> FFmpegResult {
         let output_path = PathBuf::from(output_path).join("%06d.jpg");
         let output_path = output_path.to_str().unwrap();
         let video_path = video_path.to_str().unwrap();
         let output = Command::new("ffmpeg")
+            .args(sampling.input_args())
+            .args(["-i", video_path])
+            .args(sampling.output_args(&[]))
             .args([
-                "-skip_frame",
-                "nokey",
-                "-i",
-                video_path,
-                "-fps_mode",
-                "passthrough",
                 "-f",
                 "image2",
                 "-frame_pts",
                 "true",
+                // pts in frames of the source rate, whatever the sampling
+                "-enc_time_base",
+                &format!("1:{}", fps.max(1)),
                 "-qscale:v",
                 "2",
                 "-qmin",
@@ -59,6 +149,41 @@ pub mod ffmpeg_utils {
             Err(e) => return FFmpegResult::Failure(e.to_string()),
         };
     }
+    /// uses ffmpeg to save the frame shown at `seconds`, the format follows the extension
+    pub fn extract_frame(video_path: &Path, seconds: f64, output_path: &Path) -> FFmpegResult {
+        let video_path = video_path.to_str().unwrap();
+        let output_path = output_path.to_str().unwrap();
+        let output = Command::new("ffmpeg")
+            .args(["-ss", &seconds.to_string(), "-i", video_path])
+            .args(["-frames:v", "1", "-y", output_path])
+            .output();
+        match output {
+            Ok(output) => match output.status.success() {
+                true => {
+                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+                    return FFmpegResult::Success(stdout);
+                }
+                false => {
+                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+                    return FFmpegResult::Failure(stderr);
+                }
+            },
+            Err(e) => return FFmpegResult::Failure(e.to_string()),
+        };
+    }
+    /// decodes the frame shown at `seconds`, seeking is frame accurate as the video is decoded
+    pub fn frame_at(video_path: &Path, seconds: f64) -> Result<image::DynamicImage, String> {
+        let video_path = video_path.to_str().unwrap();
+        let output = Command::new("ffmpeg")
+            .args(["-ss", &format!("{:.3}", seconds), "-i", video_path])
+            .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() || output.stdout.is_empty() {
+            return Err(String::from_utf8_lossy(&output.stderr).to_string());
+        }
+        return image::load_from_memory(&output.stdout).map_err(|e| e.to_string());
+    }
     /// converts a video a .wav file
     pub fn conv2wav(video_path: &Path, output_path: &Path) -> FFmpegResult {
         let video_path = video_path.to_str().unwrap();
@@ -146,6 +271,79 @@ pub mod ffmpeg_utils {
             }
         }
     }
+    /// lists the subtitle streams of a video
+    pub fn get_subtitle_streams(video_path: &Path) -> Result<Vec<SubtitleStream>, FFprobeResult> {
+        let video_path = video_path.to_str().unwrap();
+        let output = Command::new("ffprobe")
+            .args([
+                "-v",
+                "error",
+                "-select_streams",
+                "s",
+                "-show_entries",
+                "stream=codec_name:stream_tags=language",
+                "-of",
+                "json",
+                video_path,
+            ])
+            .stdout(Stdio::piped())
+            .output();
+        match output {
+            Ok(output) => {
+                let probe_json = String::from_utf8_lossy(&output.stdout);
+                let probe: serde_json::Value = serde_json::from_str(&probe_json)
+                    .map_err(|e| FFprobeResult::Failure(e.to_string()))?;
+                let streams = probe["streams"].as_array().cloned().unwrap_or_default();
+                return Ok(streams
+                    .iter()
+                    .enumerate()
+                    .map(|(position, stream)| SubtitleStream {
+                        position,
+                        codec: stream["codec_name"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        language: stream["tags"]["language"].as_str().map(String::from),
+                    })
+                    .collect());
+            }
+            Err(e) => return Err(FFprobeResult::Failure(e.to_string())),
+        }
+    }
+    /// converts a text subtitle stream to an .srt file
+    pub fn extract_subtitles(
+        video_path: &Path,
+        position: usize,
+        output_path: &Path,
+    ) -> FFmpegResult {
+        let video_path = video_path.to_str().unwrap();
+        let output_path = output_path.to_str().unwrap();
+        let output = Command::new("ffmpeg")
+            .args([
+                "-y",
+                "-i",
+                video_path,
+                "-map",
+                &format!("0:s:{}", position),
+                "-f",
+                "srt",
+                output_path,
+            ])
+            .output();
+        match output {
+            Ok(output) => match output.status.success() {
+                true => {
+                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+                    return FFmpegResult::Success(stdout);
+                }
+                false => {
+                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+                    return FFmpegResult::Failure(stderr);
+                }
+            },
+            Err(e) => return FFmpegResult::Failure(e.to_string()),
+        };
+    }
     fn parse_string<T: std::str::FromStr>(s: &str) -> Result<T, <T as std::str::FromStr>::Err> {
         s.parse::<T>()
     }
@@ -157,61 +355,177 @@ pub mod ffmpeg_utils {
     }
 }
 
-// !WIP
 pub mod frames_iterator {
-    use std::io::Read;
+    use std::io::{BufRead, BufReader, Read};
     use std::path::Path;
-    use std::process::{Command, Stdio};
+    use std::process::{Child, ChildStdout, Command, Stdio};
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    use image::{DynamicImage, GrayImage, RgbImage};
+
+    use super::ffmpeg_utils::{self, Sampling};
 
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum PixelFormat {
+        Gray,
+        Rgb,
+    }
+
+    impl PixelFormat {
+        pub fn channels(&self) -> usize {
+            match self {
+                PixelFormat::Gray => 1,
+                PixelFormat::Rgb => 3,
+            }
+        }
+        fn ffmpeg_name(&self) -> &'static str {
+            match self {
+                PixelFormat::Gray => "gray",
+                PixelFormat::Rgb => "rgb24",
+            }
+        }
+    }
+
+    /// one decoded frame, `data` is exactly `width * height * channels` bytes
+    pub struct Frame {
+        /// presentation time in seconds
+        pub pts: f64,
+        pub width: u32,
+        pub height: u32,
+        pub format: PixelFormat,
+        pub data: Vec<u8>,
+    }
+
+    impl Frame {
+        pub fn into_image(self) -> DynamicImage {
+            // the iterator always reads a full frame, so the buffer size is right
+            match self.format {
+                PixelFormat::Gray => DynamicImage::ImageLuma8(
+                    GrayImage::from_raw(self.width, self.height, self.data).unwrap(),
+                ),
+                PixelFormat::Rgb => DynamicImage::ImageRgb8(
+                    RgbImage::from_raw(self.width, self.height, self.data).unwrap(),
+                ),
+            }
+        }
+    }
+
+    /// decodes the sampled frames of a video from an ffmpeg pipe, nothing is written to disk.
+    /// the frame times come from ffmpeg's `showinfo` filter on stderr
     pub struct VideoFramesIterator {
-        ffmpeg_command: Option<std::process::Child>,
-        buffer: [u8; 1024],
+        ffmpeg: Option<Child>,
+        stdout: Option<ChildStdout>,
+        pts: Receiver<f64>,
+        last_pts: f64,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
     }
     impl VideoFramesIterator {
-        pub fn new(video_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        pub fn new(
+            video_path: &Path,
+            sampling: Sampling,
+            format: PixelFormat,
+        ) -> Result<Self, Box<dyn std::error::Error>> {
+            return Self::cropped(video_path, sampling, format, None);
+        }
+        /// like `new`, but only decodes the `(left, top, width, height)` part of every frame
+        pub fn cropped(
+            video_path: &Path,
+            sampling: Sampling,
+            format: PixelFormat,
+            crop: Option<(u32, u32, u32, u32)>,
+        ) -> Result<Self, Box<dyn std::error::Error>> {
+            let (width, height) = match crop {
+                Some((_, _, width, height)) => (width as u64, height as u64),
+                None => ffmpeg_utils::get_video_dims(video_path).map_err(|e| format!("{:?}", e))?,
+            };
+            let crop = crop.map(|(left, top, width, height)| {
+                format!("crop={}:{}:{}:{}", width, height, left, top)
+            });
+            let filters: Vec<&str> = crop
+                .iter()
+                .map(String::as_str)
+                .chain(["showinfo"])
+                .collect();
             let video_path = video_path.to_str().unwrap();
-            let ffmpeg_command = Command::new("ffmpeg")
-                .args([
-                    "-i",
-                    video_path,
-                    "-f",
-                    "image2pipe",
-                    "-pix_fmt",
-                    "rgb8",
-                    "-vcodec",
-                    "rawvideo",
-                    "-",
-                ])
+            let mut ffmpeg = Command::new("ffmpeg")
+                .arg("-hide_banner")
+                .args(sampling.input_args())
+                .args(["-i", video_path])
+                .args(sampling.output_args(&filters))
+                .args(["-f", "rawvideo", "-pix_fmt", format.ffmpeg_name(), "-"])
                 .stdout(Stdio::piped())
-                .stderr(Stdio::null())
+                .stderr(Stdio::piped())
                 .spawn()?;
-
+            let stdout = ffmpeg.stdout.take();
+            let stderr = ffmpeg.stderr.take().unwrap();
+            let (sender, pts) = mpsc::channel();
+            // stderr has to be drained even if nobody reads the times, or ffmpeg blocks
+            thread::spawn(move || {
+                for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                    if let Some(pts) = parse_pts_time(&line) {
+                        let _ = sender.send(pts);
+                    }
+                }
+            });
             Ok(Self {
-                ffmpeg_command: Some(ffmpeg_command),
-                buffer: [0u8; 1024],
+                ffmpeg: Some(ffmpeg),
+                stdout,
+                pts,
+                last_pts: 0.0,
+                width: width as u32,
+                height: height as u32,
+                format,
             })
         }
+        pub fn frame_size(&self) -> usize {
+            return self.width as usize * self.height as usize * self.format.channels();
+        }
     }
     impl Iterator for VideoFramesIterator {
-        type Item = Vec<u8>;
+        type Item = Frame;
 
         fn next(&mut self) -> Option<Self::Item> {
-            let mut frame_data = Vec::new();
-
-            if let Some(ref mut ffmpeg) = self.ffmpeg_command {
-                let size = match ffmpeg.stdout.as_mut().unwrap().read(&mut self.buffer) {
-                    Ok(size) if size > 0 => size,
-                    _ => {
-                        // The child process has finished or there was an error
-                        self.ffmpeg_command = None;
-                        return None;
-                    }
-                };
-
-                frame_data.extend_from_slice(&self.buffer[..size]);
+            let frame_size = self.frame_size();
+            let stdout = self.stdout.as_mut()?;
+            let mut data = vec![0u8; frame_size];
+            if stdout.read_exact(&mut data).is_err() {
+                // the video ended (a partial frame is dropped) or ffmpeg failed
+                self.stdout = None;
+                if let Some(mut ffmpeg) = self.ffmpeg.take() {
+                    let _ = ffmpeg.wait();
+                }
+                return None;
+            }
+            // showinfo logs a frame before it is written to the pipe
+            let pts = self.pts.recv().unwrap_or(self.last_pts);
+            self.last_pts = pts;
+            return Some(Frame {
+                pts,
+                width: self.width,
+                height: self.height,
+                format: self.format,
+                data,
+            });
+        }
+    }
+    impl Drop for VideoFramesIterator {
+        fn drop(&mut self) {
+            if let Some(mut ffmpeg) = self.ffmpeg.take() {
+                let _ = ffmpeg.kill();
+                let _ = ffmpeg.wait();
             }
+        }
+    }
 
-            return Some(frame_data);
+    /// reads `pts_time` from a `showinfo` log line
+    pub fn parse_pts_time(line: &str) -> Option<f64> {
+        if !line.contains("showinfo") {
+            return None;
         }
+        let (_, rest) = line.split_once("pts_time:")?;
+        return rest.split_whitespace().next()?.parse::<f64>().ok();
     }
 }